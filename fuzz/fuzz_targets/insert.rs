@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use insert_dylib_rs::arch::read_archs;
+use insert_dylib_rs::opts::Opts;
+use insert_dylib_rs::patch::patch_buffer;
+use insert_dylib_rs::report::{Format, Reporter};
+use libfuzzer_sys::fuzz_target;
+
+// Insert a dylib and strip the code signature in memory, whatever is written has to parse again
+fuzz_target!(|data: &[u8]| {
+    let mut options = Opts::default();
+    options.dylib_path = "@rpath/libfuzz.dylib".to_string();
+    options.strip_codesign = true;
+
+    // collected rather than printed, and dropped after every input
+    let mut reporter = Reporter::new(Format::Json);
+    let mut binary = data.to_vec();
+    if patch_buffer(&mut binary, &options, &[], &mut reporter).is_ok() {
+        if let Err(e) = read_archs(&mut Cursor::new(binary)) {
+            panic!("patched binary doesn't parse: {}", e);
+        }
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use insert_dylib_rs::arch::read_archs;
+use libfuzzer_sys::fuzz_target;
+
+// Fat header, Mach-O header and load command parsing must reject anything malformed with an error
+fuzz_target!(|data: &[u8]| {
+    let _ = read_archs(&mut Cursor::new(data.to_vec()));
+});
@@ -0,0 +1,263 @@
+use std::io::SeekFrom;
+
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::ops::bounds::{check_slice, read_fat_archs};
+use crate::ops::read_mach_header;
+
+/// CPU type, subtype and deployment target of a single Mach-O slice
+#[derive(Debug)]
+pub struct SliceArch {
+    pub cputype: u32,
+    pub cpusubtype: u32,
+    /// `(platform, minos)` from LC_BUILD_VERSION, if the slice has one
+    pub build_version: Option<(u32, u32)>,
+}
+
+impl SliceArch {
+    pub fn name(&self) -> String {
+        arch_name(self.cputype, self.cpusubtype)
+    }
+}
+
+/// Offset and size of a single Mach-O slice in a thin or fat file
+#[derive(Debug, Clone, Copy)]
+pub struct SliceRange {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Locate every slice of a thin or fat Mach-O file, checking each one with `check_slice`
+///
+/// Returns the slices along with whether the file is a fat binary.
+pub fn read_slice_ranges<F: InsertDylibFileExt>(file: &mut F) -> Result<(Vec<SliceRange>, bool)> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut magic_buffer = [0u8; 4];
+    file.fpeek(&mut magic_buffer)?;
+    let magic = u32::from_be_bytes(magic_buffer);
+
+    let (slices, fat) = match magic {
+        MH_CIGAM_64 | MH_MAGIC_64 | MH_CIGAM | MH_MAGIC => {
+            let file_len = file.flen()?;
+            let slice = SliceRange {
+                offset: 0,
+                size: file_len,
+            };
+            (vec![slice], false)
+        }
+        FAT_MAGIC | FAT_CIGAM => {
+            let fat_endian = match magic {
+                FAT_MAGIC => Endian::Big,
+                _ => Endian::Little,
+            };
+            let (_, fat_archs) = read_fat_archs(file, fat_endian)?;
+            let slices = fat_archs
+                .iter()
+                .map(|fat_arch| SliceRange {
+                    offset: fat_arch.offset as u64,
+                    size: fat_arch.size as u64,
+                })
+                .collect();
+            (slices, true)
+        }
+        _ => return Err(InsertDylibError::BadMagic(magic)),
+    };
+
+    for slice in &slices {
+        check_slice(file, slice.offset, slice.size)?;
+    }
+
+    Ok((slices, fat))
+}
+
+/// Read the architecture of every slice in a thin or fat Mach-O file
+///
+/// Returns an empty Vec if the file is not a Mach-O binary.
+pub fn read_archs<F: InsertDylibFileExt>(file: &mut F) -> Result<Vec<SliceArch>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut magic_buffer = [0u8; 4];
+    file.fpeek(&mut magic_buffer)?;
+    if Endian::from_magic(magic_buffer).is_none() {
+        return Ok(Vec::new());
+    }
+
+    let (slices, _) = read_slice_ranges(file)?;
+    slices
+        .iter()
+        .map(|slice| read_slice_arch(file, slice.offset))
+        .collect()
+}
+
+/// Read the architecture of the Mach-O slice starting at `header_offset`
+pub fn read_slice_arch<F: InsertDylibFileExt>(
+    file: &mut F,
+    header_offset: u64,
+) -> Result<SliceArch> {
+    let (mach_header, endian) = read_mach_header(file, header_offset)?;
+
+    let mut slice_arch = SliceArch {
+        cputype: mach_header.cputype,
+        cpusubtype: mach_header.cpusubtype,
+        build_version: None,
+    };
+
+    file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for _ in 0..mach_header.ncmds {
+        let lc: LoadCommand = file.fpeek_struct(endian)?;
+
+        if lc.cmd == LC_BUILD_VERSION {
+            let cmd: BuildVersionCommand = file.fpeek_struct(endian)?;
+            slice_arch.build_version = Some((cmd.platform, cmd.minos));
+            break;
+        }
+
+        file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    Ok(slice_arch)
+}
+
+/// Check whether a dylib with slices `dylib_archs` can be loaded into `target`
+///
+/// Returns `Err` with the reason if dyld would refuse to load the dylib, or
+/// `Ok` with a list of warnings (e.g. a newer deployment target) otherwise.
+pub fn check_compatibility(
+    target: &SliceArch,
+    dylib_archs: &[SliceArch],
+) -> Result<Vec<String>, String> {
+    let candidate = dylib_archs.iter().find(|dylib_arch| {
+        dylib_arch.cputype == target.cputype
+            && cpusubtype_compatible(target.cputype, target.cpusubtype, dylib_arch.cpusubtype)
+    });
+
+    let dylib_arch = match candidate {
+        Some(dylib_arch) => dylib_arch,
+        None => {
+            let available: Vec<String> = dylib_archs.iter().map(|arch| arch.name()).collect();
+            return Err(format!(
+                "dylib has no slice loadable by {} (dylib archs: {})",
+                target.name(),
+                available.join(", ")
+            ));
+        }
+    };
+
+    let mut warnings = Vec::new();
+    if let (Some((target_platform, target_minos)), Some((dylib_platform, dylib_minos))) =
+        (target.build_version, dylib_arch.build_version)
+    {
+        if target_platform != dylib_platform {
+            warnings.push(format!(
+                "{} dylib is built for {}, but binary is built for {}",
+                target.name(),
+                platform_name(dylib_platform),
+                platform_name(target_platform)
+            ));
+        } else if dylib_minos > target_minos {
+            warnings.push(format!(
+                "{} dylib requires {} {}, but binary targets {} {}",
+                target.name(),
+                platform_name(dylib_platform),
+                format_version(dylib_minos),
+                platform_name(target_platform),
+                format_version(target_minos)
+            ));
+        }
+    }
+
+    Ok(warnings)
+}
+
+fn cpusubtype_compatible(cputype: u32, target_cpusubtype: u32, dylib_cpusubtype: u32) -> bool {
+    let target_subtype = target_cpusubtype & !CPU_SUBTYPE_MASK;
+    let dylib_subtype = dylib_cpusubtype & !CPU_SUBTYPE_MASK;
+
+    match cputype {
+        CPU_TYPE_ARM64 => {
+            let target_arm64e = target_subtype == CPU_SUBTYPE_ARM64E;
+            let dylib_arm64e = dylib_subtype == CPU_SUBTYPE_ARM64E;
+            if target_arm64e != dylib_arm64e {
+                return false;
+            }
+            if target_arm64e
+                && target_cpusubtype & CPU_SUBTYPE_PTRAUTH_ABI != 0
+                && dylib_cpusubtype & CPU_SUBTYPE_PTRAUTH_ABI != 0
+            {
+                // both slices are versioned, so the ptrauth ABI versions must match
+                return ptrauth_abi_version(target_cpusubtype)
+                    == ptrauth_abi_version(dylib_cpusubtype);
+            }
+            true
+        }
+        CPU_TYPE_X86 | CPU_TYPE_X86_64 => {
+            // an x86_64h process can load plain x86_64 dylibs, but not the other way round
+            dylib_subtype == CPU_SUBTYPE_X86_ALL || dylib_subtype == target_subtype
+        }
+        _ => dylib_subtype == 0 || dylib_subtype == target_subtype,
+    }
+}
+
+fn ptrauth_abi_version(cpusubtype: u32) -> u32 {
+    (cpusubtype & CPU_SUBTYPE_ARM64_PTR_AUTH_MASK) >> 24
+}
+
+pub fn arch_name(cputype: u32, cpusubtype: u32) -> String {
+    let subtype = cpusubtype & !CPU_SUBTYPE_MASK;
+    let name = match (cputype, subtype) {
+        (CPU_TYPE_X86, _) => "i386",
+        (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_H) => "x86_64h",
+        (CPU_TYPE_X86_64, _) => "x86_64",
+        (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64E) => "arm64e",
+        (CPU_TYPE_ARM64, _) => "arm64",
+        (CPU_TYPE_ARM, 9) => "armv7",
+        (CPU_TYPE_ARM, 11) => "armv7s",
+        (CPU_TYPE_ARM, _) => "arm",
+        (CPU_TYPE_POWERPC, _) => "ppc",
+        (CPU_TYPE_POWERPC64, _) => "ppc64",
+        _ => return format!("cputype {} cpusubtype {}", cputype, cpusubtype),
+    };
+
+    if cputype == CPU_TYPE_ARM64
+        && subtype == CPU_SUBTYPE_ARM64E
+        && cpusubtype & CPU_SUBTYPE_PTRAUTH_ABI != 0
+    {
+        format!(
+            "{} (ptrauth ABI v{})",
+            name,
+            ptrauth_abi_version(cpusubtype)
+        )
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn platform_name(platform: u32) -> String {
+    match platform {
+        PLATFORM_MACOS => "macOS".to_string(),
+        PLATFORM_IOS => "iOS".to_string(),
+        PLATFORM_TVOS => "tvOS".to_string(),
+        PLATFORM_WATCHOS => "watchOS".to_string(),
+        PLATFORM_BRIDGEOS => "bridgeOS".to_string(),
+        PLATFORM_MACCATALYST => "Mac Catalyst".to_string(),
+        PLATFORM_IOSSIMULATOR => "iOS Simulator".to_string(),
+        PLATFORM_TVOSSIMULATOR => "tvOS Simulator".to_string(),
+        PLATFORM_WATCHOSSIMULATOR => "watchOS Simulator".to_string(),
+        PLATFORM_DRIVERKIT => "DriverKit".to_string(),
+        PLATFORM_VISIONOS => "visionOS".to_string(),
+        PLATFORM_VISIONOSSIMULATOR => "visionOS Simulator".to_string(),
+        _ => format!("platform {}", platform),
+    }
+}
+
+/// Format a `xxxx.yy.zz` nibble-encoded version as used by LC_BUILD_VERSION
+pub fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        version >> 16,
+        (version >> 8) & 0xff,
+        version & 0xff
+    )
+}
@@ -0,0 +1,569 @@
+use std::path::Path;
+
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, ErrorKind, SubCommand};
+
+use crate::ops::rewrite::RewriteRule;
+use crate::opts::{Operation, Opts};
+use crate::report::Format;
+
+pub fn parse_arg() -> Opts {
+    let matches = App::new("Insert Dylib")
+        .version("0.2.0")
+        .author("Cocoa <i@uwucocoa.moe>")
+        .about("Insert dylib into Mach-O binary")
+        .after_help("Without a subcommand, the options above work as in earlier versions, inserting the dylib unless another operation is given.")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("dylib_path")
+                .short("d")
+                .long("dylib")
+                .required_unless_one(&["install_name", "rewrite_prefix", "rewrite_glob", "dedupe"])
+                .help("dylib path")
+                .takes_value(true),
+        )
+        .arg(dylib_file_arg())
+        .arg(
+            Arg::with_name("binary_path")
+                .short("b")
+                .long("binary")
+                .required(true)
+                .help("binary file")
+                .takes_value(true),
+        )
+        .arg(weak_arg())
+        .arg(
+            Arg::with_name("make_weak")
+                .long("make-weak")
+                .multiple(false)
+                .conflicts_with_all(&["weak", "make_strong"])
+                .help("Change the existing load command for the dylib to LC_LOAD_WEAK_DYLIB"),
+        )
+        .arg(
+            Arg::with_name("make_strong")
+                .long("make-strong")
+                .multiple(false)
+                .conflicts_with("weak")
+                .help("Change the existing load command for the dylib to LC_LOAD_DYLIB"),
+        )
+        .arg(
+            install_name_arg()
+                .conflicts_with_all(&["dylib_path", "weak", "make_weak", "make_strong"]),
+        )
+        .arg(
+            rewrite_prefix_arg()
+                .conflicts_with_all(&["dylib_path", "install_name", "weak", "make_weak", "make_strong"]),
+        )
+        .arg(
+            rewrite_glob_arg()
+                .conflicts_with_all(&["dylib_path", "install_name", "weak", "make_weak", "make_strong"]),
+        )
+        .arg(
+            dedupe_arg()
+                .conflicts_with_all(&["dylib_path", "install_name", "rewrite_prefix", "rewrite_glob", "weak", "make_weak", "make_strong"]),
+        )
+        .arg(resolve_rpath_arg())
+        .args(&output_args())
+        .arg(strip_codesign_arg())
+        .arg(format_arg())
+        .subcommand(
+            SubCommand::with_name("insert")
+                .about("Add a load command for a dylib")
+                .arg(dylib_arg())
+                .arg(binary_arg().index(2))
+                .arg(format_arg())
+                .arg(dylib_file_arg())
+                .arg(weak_arg())
+                .arg(strip_codesign_arg())
+                .args(&output_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Remove the load commands for a dylib")
+                .arg(dylib_arg())
+                .arg(binary_arg().index(2))
+                .arg(format_arg())
+                .args(&output_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("change")
+                .about("Change existing load commands")
+                .arg(binary_arg().index(1))
+                .arg(format_arg())
+                .arg(
+                    Arg::with_name("make_weak")
+                        .long("make-weak")
+                        .value_name("DYLIB")
+                        .help("Change the load command for DYLIB to LC_LOAD_WEAK_DYLIB")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("make_strong")
+                        .long("make-strong")
+                        .value_name("DYLIB")
+                        .help("Change the load command for DYLIB to LC_LOAD_DYLIB")
+                        .takes_value(true),
+                )
+                .arg(install_name_arg())
+                .arg(rewrite_prefix_arg())
+                .arg(rewrite_glob_arg())
+                .arg(dedupe_arg())
+                .arg(resolve_rpath_arg())
+                .group(
+                    ArgGroup::with_name("change")
+                        .args(&["make_weak", "make_strong", "install_name", "rewrite_prefix", "rewrite_glob", "dedupe"])
+                        .required(true),
+                )
+                .group(
+                    // rewrite rules of both kinds may be combined
+                    ArgGroup::with_name("rewrite")
+                        .args(&["rewrite_prefix", "rewrite_glob"])
+                        .multiple(true)
+                        .conflicts_with_all(&["make_weak", "make_strong", "install_name", "dedupe"]),
+                )
+                .args(&output_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List the dylibs and rpaths of a binary, like otool -L")
+                .arg(binary_arg().index(1))
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Summarize every slice of a binary")
+                .arg(binary_arg().index(1))
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("dump")
+                .about("Print every load command with its fields, like otool -l")
+                .arg(binary_arg().index(1))
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check the load commands, segments and fat slices against the rules dyld enforces")
+                .arg(binary_arg().index(1))
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare the load commands of two binaries, slice by slice")
+                .arg(binary_arg().index(1))
+                .arg(
+                    Arg::with_name("other_path")
+                        .value_name("OTHER")
+                        .index(2)
+                        .required(true)
+                        .help("binary to compare with"),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Print the provenance records written with --provenance")
+                .arg(binary_arg().index(1))
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("strip")
+                .about("Remove the code signature")
+                .arg(binary_arg().index(1))
+                .arg(format_arg())
+                .args(&output_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("sign")
+                .about("Sign a binary ad-hoc, like codesign -s -")
+                .arg(binary_arg().index(1))
+                .arg(format_arg())
+                .arg(
+                    Arg::with_name("identifier")
+                        .short("i")
+                        .long("identifier")
+                        .value_name("IDENTIFIER")
+                        .help("Signing identifier, defaults to the file name of the binary")
+                        .takes_value(true),
+                )
+                .args(&output_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Patch a binary with a patch file saved by --export-patch")
+                .arg(patch_arg())
+                .arg(binary_arg().index(2))
+                .arg(format_arg())
+                .args(&destination_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("revert")
+                .about("Restore the original binary from the patched one and its patch file")
+                .arg(patch_arg())
+                .arg(binary_arg().index(2))
+                .arg(format_arg())
+                .args(&destination_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Restore the input binary from the output and the write log saved by --write-log")
+                .arg(
+                    Arg::with_name("write_log_path")
+                        .value_name("WRITE_LOG")
+                        .index(1)
+                        .required(true)
+                        .help("write log saved with --write-log"),
+                )
+                .arg(binary_arg().index(2))
+                .arg(format_arg())
+                .args(&destination_args()),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("insert", Some(sub_matches)) => {
+            let mut options = parse_common(sub_matches);
+            options.dylib_file = sub_matches.value_of("dylib_file").map(String::from);
+            options.weak = sub_matches.is_present("weak");
+            options.strip_codesign = sub_matches.is_present("strip_codesign");
+            options
+        }
+        ("remove", Some(sub_matches)) => Opts {
+            operation: Operation::Remove,
+            ..parse_common(sub_matches)
+        },
+        ("change", Some(sub_matches)) => {
+            let mut options = parse_common(sub_matches);
+            if let Some(dylib_path) = sub_matches.value_of("make_weak") {
+                options.dylib_path = dylib_path.to_string();
+                options.operation = Operation::SetWeak(true);
+            } else if let Some(dylib_path) = sub_matches.value_of("make_strong") {
+                options.dylib_path = dylib_path.to_string();
+                options.operation = Operation::SetWeak(false);
+            } else {
+                options.operation = parse_change_operation(sub_matches);
+            }
+            options
+        }
+        ("list", Some(sub_matches)) => Opts {
+            operation: Operation::List,
+            ..parse_common(sub_matches)
+        },
+        ("info", Some(sub_matches)) => Opts {
+            operation: Operation::Info,
+            ..parse_common(sub_matches)
+        },
+        ("dump", Some(sub_matches)) => Opts {
+            operation: Operation::Dump,
+            ..parse_common(sub_matches)
+        },
+        ("check", Some(sub_matches)) => Opts {
+            operation: Operation::Check,
+            ..parse_common(sub_matches)
+        },
+        ("diff", Some(sub_matches)) => Opts {
+            operation: Operation::Diff(sub_matches.value_of("other_path").unwrap().into()),
+            ..parse_common(sub_matches)
+        },
+        ("history", Some(sub_matches)) => Opts {
+            operation: Operation::History,
+            ..parse_common(sub_matches)
+        },
+        ("strip", Some(sub_matches)) => Opts {
+            operation: Operation::StripSignature,
+            ..parse_common(sub_matches)
+        },
+        ("sign", Some(sub_matches)) => {
+            let options = parse_common(sub_matches);
+            let identifier = match sub_matches.value_of("identifier") {
+                Some(identifier) => identifier.to_string(),
+                None => default_identifier(&options.binary_path),
+            };
+            Opts {
+                operation: Operation::Sign { identifier },
+                ..options
+            }
+        }
+        ("apply", Some(sub_matches)) => Opts {
+            operation: Operation::ApplyPatch(sub_matches.value_of("patch_path").unwrap().into()),
+            ..parse_common(sub_matches)
+        },
+        ("revert", Some(sub_matches)) => Opts {
+            operation: Operation::RevertPatch(sub_matches.value_of("patch_path").unwrap().into()),
+            ..parse_common(sub_matches)
+        },
+        ("undo", Some(sub_matches)) => Opts {
+            operation: Operation::UndoWriteLog(
+                sub_matches.value_of("write_log_path").unwrap().into(),
+            ),
+            ..parse_common(sub_matches)
+        },
+        _ => parse_legacy(&matches),
+    }
+}
+
+/// Options of the flat command line without a subcommand
+fn parse_legacy(matches: &ArgMatches) -> Opts {
+    let mut options = parse_common(matches);
+    options.dylib_file = matches.value_of("dylib_file").map(String::from);
+
+    options.weak = matches.occurrences_of("weak") == 1;
+    if matches.occurrences_of("make_weak") == 1 {
+        options.operation = Operation::SetWeak(true);
+    } else if matches.occurrences_of("make_strong") == 1 {
+        options.operation = Operation::SetWeak(false);
+    } else {
+        options.operation = parse_change_operation(matches);
+    }
+    options.strip_codesign = matches.occurrences_of("strip_codesign") == 1;
+    options
+}
+
+/// Operation given by `--id`, the rewrite rules or `--dedupe`, inserting the dylib otherwise
+fn parse_change_operation(matches: &ArgMatches) -> Operation {
+    if let Some(install_name) = matches.value_of("install_name") {
+        Operation::SetId(install_name.to_string())
+    } else if matches.is_present("rewrite_prefix") || matches.is_present("rewrite_glob") {
+        Operation::Rewrite(parse_rewrite_rules(matches))
+    } else if matches.occurrences_of("dedupe") == 1 {
+        Operation::Dedupe {
+            resolve_rpath: matches.occurrences_of("resolve_rpath") == 1,
+        }
+    } else {
+        Operation::Insert
+    }
+}
+
+/// The dylib and binary paths and the output options, which are shared by all commands
+fn parse_common(matches: &ArgMatches) -> Opts {
+    let mut options = Opts::default();
+    options.dylib_path = String::from(matches.value_of("dylib_path").unwrap_or_default());
+    options.binary_path = matches.value_of("binary_path").unwrap().into();
+
+    options.overwrite = matches.occurrences_of("overwrite") == 1;
+    options.inplace = matches.occurrences_of("inplace") == 1;
+    options.backup_suffix = matches.value_of("backup_suffix").map(String::from);
+
+    let mut default_output_path = String::new();
+    default_output_path.push_str(&options.binary_path);
+    if !options.inplace {
+        default_output_path.push_str("_patched");
+    }
+    options.output_path = String::from(matches.value_of("output").unwrap_or(&*default_output_path));
+
+    options.preserve_mtime = matches.occurrences_of("preserve_mtime") == 1;
+    options.dry_run = matches.occurrences_of("dry_run") == 1;
+    options.write_log = matches.value_of("write_log").map(String::from);
+    options.export_patch = matches.value_of("export_patch").map(String::from);
+    options.provenance = matches.occurrences_of("provenance") == 1;
+    options.verify_output = matches.occurrences_of("verify_output") == 1;
+    options.all_yes = matches.occurrences_of("all_yes") == 1;
+    options.format = match matches.value_of("format") {
+        Some("json") => Format::Json,
+        _ => Format::Text,
+    };
+    options
+}
+
+/// File name of the binary without its extension, as used by `codesign`
+fn default_identifier(binary_path: &str) -> String {
+    Path::new(binary_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| binary_path.to_string())
+}
+
+fn dylib_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("dylib_path")
+        .value_name("DYLIB")
+        .required(true)
+        .index(1)
+        .help("dylib path")
+}
+
+fn binary_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("binary_path")
+        .value_name("BINARY")
+        .required(true)
+        .help("binary file")
+}
+
+fn patch_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("patch_path")
+        .value_name("PATCH")
+        .index(1)
+        .required(true)
+        .help("patch file saved with --export-patch")
+}
+
+fn dylib_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("dylib_file")
+        .long("dylib-file")
+        .value_name("FILE")
+        .required(false)
+        .help("local copy of the dylib, used to check architecture compatibility (defaults to the dylib path if it exists)")
+        .takes_value(true)
+}
+
+fn weak_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("weak")
+        .long("weak")
+        .multiple(false)
+        .help("Sets LC_LOAD_WEAK_DYLIB")
+}
+
+fn install_name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("install_name")
+        .long("id")
+        .value_name("INSTALL_NAME")
+        .help("Change the install name (LC_ID_DYLIB) of the binary")
+        .takes_value(true)
+}
+
+fn rewrite_prefix_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("rewrite_prefix")
+        .long("rewrite-prefix")
+        .value_name("FROM=TO")
+        .multiple(true)
+        .number_of_values(1)
+        .help("Replace the leading FROM in dylib and rpath paths with TO, can be given multiple times")
+        .takes_value(true)
+}
+
+fn rewrite_glob_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("rewrite_glob")
+        .long("rewrite-glob")
+        .value_name("PATTERN=REPLACEMENT")
+        .multiple(true)
+        .number_of_values(1)
+        .help("Rewrite dylib and rpath paths matching the glob PATTERN (`*`, `?`) to REPLACEMENT, where $1, $2, ... refer to the wildcards, can be given multiple times")
+        .takes_value(true)
+}
+
+fn dedupe_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("dedupe")
+        .long("dedupe")
+        .multiple(false)
+        .help("Remove load commands for dylibs that are already loaded under another spelling")
+}
+
+fn resolve_rpath_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("resolve_rpath")
+        .long("resolve-rpath")
+        .multiple(false)
+        .requires("dedupe")
+        .help("Resolve @rpath/ against LC_RPATH when looking for duplicate dylibs")
+}
+
+fn strip_codesign_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("strip_codesign")
+        .long("strip-codesign")
+        .multiple(false)
+        .help("Strip codesign")
+}
+
+fn format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .long("format")
+        .value_name("FORMAT")
+        .possible_values(&["text", "json"])
+        .default_value("text")
+        .help("Print messages as text, or the result as a single JSON document")
+        .takes_value(true)
+}
+
+/// Where and how the output is written, shared by every command that writes a binary
+fn destination_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("OUTPUT")
+            .required(false)
+            .help("output path")
+            .takes_value(true),
+        Arg::with_name("overwrite")
+            .long("overwrite")
+            .multiple(false)
+            .help("Overwrite existent file"),
+        Arg::with_name("inplace")
+            .long("inplace")
+            .multiple(false)
+            .requires("overwrite")
+            .conflicts_with("output")
+            .help("Patch the binary itself instead of writing a new file, requires --overwrite"),
+        Arg::with_name("backup_suffix")
+            .long("backup-suffix")
+            .value_name("SUFFIX")
+            .requires("inplace")
+            .help("Keep the original binary next to it with this suffix, e.g. .orig")
+            .takes_value(true),
+        Arg::with_name("preserve_mtime")
+            .long("preserve-mtime")
+            .multiple(false)
+            .help("Keep the modification time of the input binary"),
+    ]
+}
+
+fn output_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = destination_args();
+    args.extend(vec![
+        Arg::with_name("dry_run")
+            .long("dry-run")
+            .multiple(false)
+            .help("Show what would change without writing anything"),
+        Arg::with_name("write_log")
+            .long("write-log")
+            .value_name("FILE")
+            .conflicts_with("dry_run")
+            .help("Save every write to the binary with the bytes it replaced as JSON, for undo")
+            .takes_value(true),
+        Arg::with_name("export_patch")
+            .long("export-patch")
+            .value_name("FILE")
+            .conflicts_with("dry_run")
+            .help("Save a compact patch from the binary to the output, for apply and revert")
+            .takes_value(true),
+        Arg::with_name("provenance")
+            .long("provenance")
+            .multiple(false)
+            .help("Record the tool version, the operation and the original slice hashes in an LC_NOTE"),
+        Arg::with_name("verify_output")
+            .long("verify-output")
+            .multiple(false)
+            .help("Run the checks of the check command on the output and refuse to write it if it breaks a rule the input doesn't"),
+        Arg::with_name("all_yes")
+            .long("all-yes")
+            .multiple(false)
+            .help("Yes to all"),
+    ]);
+    args
+}
+
+/// Collect the rewrite rules in the order they were given on the command line
+fn parse_rewrite_rules(matches: &ArgMatches) -> Vec<RewriteRule> {
+    let mut rules: Vec<(usize, Result<RewriteRule, String>)> = Vec::new();
+    if let (Some(values), Some(indices)) = (
+        matches.values_of("rewrite_prefix"),
+        matches.indices_of("rewrite_prefix"),
+    ) {
+        rules.extend(indices.zip(values.map(RewriteRule::parse_prefix)));
+    }
+    if let (Some(values), Some(indices)) = (
+        matches.values_of("rewrite_glob"),
+        matches.indices_of("rewrite_glob"),
+    ) {
+        rules.extend(indices.zip(values.map(RewriteRule::parse_glob)));
+    }
+    rules.sort_by_key(|(index, _)| *index);
+
+    rules
+        .into_iter()
+        .map(|(_, rule)| {
+            rule.unwrap_or_else(|e| {
+                clap::Error::with_description(&e, ErrorKind::InvalidValue).exit()
+            })
+        })
+        .collect()
+}
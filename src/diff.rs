@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::arch::{read_slice_arch, read_slice_ranges, SliceRange};
+use crate::error::Result;
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::ops::codesign::{read_signature_state, SignatureState};
+use crate::ops::dump::{dump_load_commands, CommandDetail, LoadCommandDump};
+use crate::ops::read_mach_header;
+use crate::utils::{filetype_name, get_segname, load_command_name};
+
+/// The parts of a slice that are compared
+#[derive(Debug)]
+pub struct SliceImage {
+    pub arch: String,
+    pub range: SliceRange,
+    pub header: MachHeader,
+    pub commands: Vec<LoadCommandDump>,
+    pub signature: SignatureState,
+}
+
+/// The parts of a thin or fat binary that are compared
+#[derive(Debug)]
+pub struct BinaryImage {
+    pub len: u64,
+    pub fat: bool,
+    pub slices: Vec<SliceImage>,
+}
+
+/// Read the header and load commands of every slice
+pub fn read_image<F: InsertDylibFileExt>(binary_file: &mut F) -> Result<BinaryImage> {
+    let len = binary_file.flen()?;
+    let (ranges, fat) = read_slice_ranges(binary_file)?;
+
+    let mut slices = Vec::new();
+    for range in ranges {
+        let (header, _) = read_mach_header(binary_file, range.offset)?;
+        slices.push(SliceImage {
+            arch: read_slice_arch(binary_file, range.offset)?.name(),
+            range,
+            header,
+            commands: dump_load_commands(binary_file, range.offset)?,
+            signature: read_signature_state(binary_file, range.offset)?,
+        });
+    }
+
+    Ok(BinaryImage { len, fat, slices })
+}
+
+/// A value that differs, `None` on the side that doesn't have it
+#[derive(Debug)]
+pub struct FieldChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum CommandChange<'a> {
+    Added(&'a LoadCommandDump),
+    Removed(&'a LoadCommandDump),
+    /// The same command, e.g. the same segment or dylib, with different fields
+    Changed {
+        before: &'a LoadCommandDump,
+        after: &'a LoadCommandDump,
+        fields: Vec<FieldChange>,
+    },
+}
+
+/// Differences between two slices of the same arch
+#[derive(Debug)]
+pub struct SliceDiff<'a> {
+    pub arch: &'a str,
+    /// `None` if the slice was added
+    pub before: Option<&'a SliceImage>,
+    /// `None` if the slice was removed
+    pub after: Option<&'a SliceImage>,
+    /// Changed fields of the fat arch and the Mach-O header, and the code signature
+    pub header: Vec<FieldChange>,
+    pub commands: Vec<CommandChange<'a>>,
+}
+
+impl SliceDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.before.is_some()
+            && self.after.is_some()
+            && self.header.is_empty()
+            && self.commands.is_empty()
+    }
+}
+
+/// Differences between two binaries, slice by slice
+#[derive(Debug)]
+pub struct BinaryDiff<'a> {
+    pub before: &'a BinaryImage,
+    pub after: &'a BinaryImage,
+    /// Every slice of both binaries, including the unchanged ones
+    pub slices: Vec<SliceDiff<'a>>,
+}
+
+impl BinaryDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.before.len == self.after.len
+            && self.before.fat == self.after.fat
+            && self.slices.iter().all(SliceDiff::is_empty)
+    }
+}
+
+/// Compare two binaries, pairing their slices by arch
+pub fn diff_images<'a>(before: &'a BinaryImage, after: &'a BinaryImage) -> BinaryDiff<'a> {
+    let mut paired = vec![false; after.slices.len()];
+    let mut slices = Vec::new();
+    for before_slice in &before.slices {
+        let after_slice = after
+            .slices
+            .iter()
+            .enumerate()
+            .find(|(index, slice)| !paired[*index] && slice.arch == before_slice.arch);
+        match after_slice {
+            Some((index, after_slice)) => {
+                paired[index] = true;
+                slices.push(diff_slices(before_slice, after_slice));
+            }
+            None => slices.push(SliceDiff {
+                arch: &before_slice.arch,
+                before: Some(before_slice),
+                after: None,
+                header: Vec::new(),
+                commands: Vec::new(),
+            }),
+        }
+    }
+    for (after_slice, _) in after
+        .slices
+        .iter()
+        .zip(paired)
+        .filter(|(_, paired)| !paired)
+    {
+        slices.push(SliceDiff {
+            arch: &after_slice.arch,
+            before: None,
+            after: Some(after_slice),
+            header: Vec::new(),
+            commands: Vec::new(),
+        });
+    }
+
+    BinaryDiff {
+        before,
+        after,
+        slices,
+    }
+}
+
+fn diff_slices<'a>(before: &'a SliceImage, after: &'a SliceImage) -> SliceDiff<'a> {
+    let header_fields = |slice: &SliceImage| -> Vec<(String, String)> {
+        vec![
+            ("offset".to_string(), format!("{:#x}", slice.range.offset)),
+            ("size".to_string(), format!("{:#x}", slice.range.size)),
+            ("filetype".to_string(), filetype_name(slice.header.filetype)),
+            ("ncmds".to_string(), slice.header.ncmds.to_string()),
+            (
+                "sizeofcmds".to_string(),
+                slice.header.sizeofcmds.to_string(),
+            ),
+            ("flags".to_string(), format!("{:#010x}", slice.header.flags)),
+            ("code signature".to_string(), slice.signature.to_string()),
+        ]
+    };
+
+    SliceDiff {
+        arch: &before.arch,
+        before: Some(before),
+        after: Some(after),
+        header: diff_fields(&header_fields(before), &header_fields(after)),
+        commands: diff_commands(&before.commands, &after.commands),
+    }
+}
+
+/// Compare `(name, value)` lists, keeping the order of `before` followed by the names only in
+/// `after`
+fn diff_fields(before: &[(String, String)], after: &[(String, String)]) -> Vec<FieldChange> {
+    let after_values: HashMap<&str, &str> = after
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let before_values: HashMap<&str, &str> = before
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+
+    let mut changes = Vec::new();
+    for (name, value) in before {
+        let after_value = after_values.get(name.as_str()).copied();
+        if after_value != Some(value.as_str()) {
+            changes.push(FieldChange {
+                name: name.clone(),
+                before: Some(value.clone()),
+                after: after_value.map(String::from),
+            });
+        }
+    }
+    for (name, value) in after {
+        if !before_values.contains_key(name.as_str()) {
+            changes.push(FieldChange {
+                name: name.clone(),
+                before: None,
+                after: Some(value.clone()),
+            });
+        }
+    }
+    changes
+}
+
+/// Every field of a load command with a unique name, repeated fields like the tools of
+/// LC_BUILD_VERSION are numbered and section fields are prefixed with the section name
+fn command_fields(command: &LoadCommandDump) -> Vec<(String, String)> {
+    let mut fields = vec![
+        ("cmd".to_string(), load_command_name(command.cmd)),
+        ("cmdsize".to_string(), command.cmdsize.to_string()),
+    ];
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (name, value) in command.fields() {
+        let count = seen.entry(name).or_default();
+        *count += 1;
+        match *count {
+            1 => fields.push((name.to_string(), value)),
+            n => fields.push((format!("{} {}", name, n), value)),
+        }
+    }
+    for section in command.sections() {
+        let sectname = section
+            .first()
+            .map(|(_, sectname)| sectname.clone())
+            .unwrap_or_default();
+        for (name, value) in section {
+            fields.push((format!("{}.{}", sectname, name), value));
+        }
+    }
+    fields
+}
+
+/// What makes two load commands the same command, so that a changed field shows up as a change
+/// rather than as a removed and an added command
+fn command_identity(command: &LoadCommandDump) -> (u32, String) {
+    match &command.detail {
+        CommandDetail::Segment { command, .. } => (LC_SEGMENT_64, get_segname(&command.segname)),
+        // making a dylib weak changes its cmd, but it is still the same dylib
+        CommandDetail::Dylib { path, .. } if command.cmd != LC_ID_DYLIB => {
+            (LC_LOAD_DYLIB, path.clone())
+        }
+        CommandDetail::Rpath(path) => (LC_RPATH, path.clone()),
+        _ => (command.cmd, String::new()),
+    }
+}
+
+/// Short description of a load command, e.g. `LC_LOAD_DYLIB /usr/lib/libSystem.B.dylib`
+pub fn describe_command(command: &LoadCommandDump) -> String {
+    let name = load_command_name(command.cmd);
+    match &command.detail {
+        CommandDetail::Segment { command, .. } => {
+            format!("{} {}", name, get_segname(&command.segname))
+        }
+        CommandDetail::Dylib { path, .. } => format!("{} {}", name, path),
+        CommandDetail::Dylinker(path) | CommandDetail::Rpath(path) => {
+            format!("{} {}", name, path)
+        }
+        CommandDetail::Note(command) => format!("{} {}", name, get_segname(&command.data_owner)),
+        _ => name,
+    }
+}
+
+/// Align two lists of load commands on their longest common subsequence, then pair up the
+/// removed and added commands in between that are the same command
+fn diff_commands<'a>(
+    before: &'a [LoadCommandDump],
+    after: &'a [LoadCommandDump],
+) -> Vec<CommandChange<'a>> {
+    let before_fields: Vec<_> = before.iter().map(command_fields).collect();
+    let after_fields: Vec<_> = after.iter().map(command_fields).collect();
+
+    // lcs[i][j] is the length of the common subsequence of before[i..] and after[j..]
+    let mut lcs = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lcs[i][j] = match before_fields[i] == after_fields[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before_fields[i] == after_fields[j] {
+            pair_changes(
+                before,
+                after,
+                &before_fields,
+                &after_fields,
+                &mut removed,
+                &mut added,
+                &mut changes,
+            );
+            i += 1;
+            j += 1;
+        } else if j < after.len() && (i == before.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            added.push(j);
+            j += 1;
+        } else {
+            removed.push(i);
+            i += 1;
+        }
+    }
+    pair_changes(
+        before,
+        after,
+        &before_fields,
+        &after_fields,
+        &mut removed,
+        &mut added,
+        &mut changes,
+    );
+
+    changes
+}
+
+/// Turn the removed and added commands between two common ones into changes
+fn pair_changes<'a>(
+    before: &'a [LoadCommandDump],
+    after: &'a [LoadCommandDump],
+    before_fields: &[Vec<(String, String)>],
+    after_fields: &[Vec<(String, String)>],
+    removed: &mut Vec<usize>,
+    added: &mut Vec<usize>,
+    changes: &mut Vec<CommandChange<'a>>,
+) {
+    let same_command: fn(&LoadCommandDump, &LoadCommandDump) -> bool =
+        |before, after| command_identity(before) == command_identity(after);
+    let same_kind: fn(&LoadCommandDump, &LoadCommandDump) -> bool =
+        |before, after| command_identity(before).0 == command_identity(after).0;
+
+    // the same command first, then commands of the same kind in order, e.g. a renamed dylib
+    let mut pairs: Vec<(usize, Option<usize>)> = removed.drain(..).map(|i| (i, None)).collect();
+    for matches in [same_command, same_kind] {
+        for (i, pair) in pairs.iter_mut().filter(|(_, pair)| pair.is_none()) {
+            if let Some(position) = added.iter().position(|j| matches(&before[*i], &after[*j])) {
+                *pair = Some(added.remove(position));
+            }
+        }
+    }
+
+    for (i, pair) in pairs {
+        match pair {
+            Some(j) => changes.push(CommandChange::Changed {
+                before: &before[i],
+                after: &after[j],
+                fields: diff_fields(&before_fields[i], &after_fields[j]),
+            }),
+            None => changes.push(CommandChange::Removed(&before[i])),
+        }
+    }
+    for j in added.drain(..) {
+        changes.push(CommandChange::Added(&after[j]));
+    }
+}
+
+fn fmt_field_change(f: &mut fmt::Formatter<'_>, indent: &str, change: &FieldChange) -> fmt::Result {
+    writeln!(
+        f,
+        "{}{}: {} -> {}",
+        indent,
+        change.name,
+        change.before.as_deref().unwrap_or("(none)"),
+        change.after.as_deref().unwrap_or("(none)")
+    )
+}
+
+impl fmt::Display for BinaryDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+        if self.before.fat != self.after.fat {
+            let kind = |fat: bool| match fat {
+                true => "fat",
+                false => "thin",
+            };
+            writeln!(
+                f,
+                "binary: {} -> {}",
+                kind(self.before.fat),
+                kind(self.after.fat)
+            )?;
+        }
+        if self.before.len != self.after.len {
+            writeln!(f, "file length: {} -> {}", self.before.len, self.after.len)?;
+        }
+
+        for slice in &self.slices {
+            match (slice.before, slice.after) {
+                (Some(_), None) => writeln!(f, "{}: removed", slice.arch)?,
+                (None, Some(_)) => writeln!(f, "{}: added", slice.arch)?,
+                _ if slice.is_empty() => writeln!(f, "{}: unchanged", slice.arch)?,
+                _ => {
+                    writeln!(f, "{}:", slice.arch)?;
+                    for change in &slice.header {
+                        fmt_field_change(f, "    ", change)?;
+                    }
+                    for change in &slice.commands {
+                        match change {
+                            CommandChange::Added(command) => {
+                                writeln!(f, "    + {}", describe_command(command))?
+                            }
+                            CommandChange::Removed(command) => {
+                                writeln!(f, "    - {}", describe_command(command))?
+                            }
+                            CommandChange::Changed { after, fields, .. } => {
+                                writeln!(f, "    ~ {}", describe_command(after))?;
+                                for change in fields {
+                                    fmt_field_change(f, "        ", change)?;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::io;
+
+use crate::utils::load_command_name;
+
+/// Everything that can make an operation on a binary fail
+#[derive(Debug)]
+pub enum InsertDylibError {
+    /// Reading or writing the file failed
+    Io(io::Error),
+    /// The file or slice doesn't start with a known Mach-O magic
+    BadMagic(u32),
+    /// The file ends in the middle of a header or load command
+    Truncated,
+    /// There are fewer than `needed` zero bytes after the load commands
+    NoSpace { needed: u64 },
+    /// The binary already has a load command for the dylib
+    AlreadyPresent(String),
+    /// LC_CODE_SIGNATURE is not the last load command, so it can't be stripped
+    SignatureNotLast,
+    /// The dylib has no slice that can be loaded into the binary
+    ArchMismatch(String),
+    /// The binary has no load command for the dylib
+    DylibNotFound(String),
+    /// The binary has no LC_ID_DYLIB
+    NotADylib,
+    /// Removing dylib load commands would change the ordinals of this many dylibs
+    OrdinalsShifted(usize),
+    /// The slice can't be signed, e.g. because __LINKEDIT is not at its end
+    Unsignable(String),
+    /// The fat header lists more archs than fit in the file
+    TooManyArchs(u32),
+    /// A fat slice lies outside of the file, overlaps another one or has a bogus alignment
+    BadFatArch {
+        index: usize,
+        offset: u32,
+        size: u32,
+        align: u32,
+    },
+    /// `sizeofcmds` runs past the end of the slice
+    LoadCommandsOutOfBounds { sizeofcmds: u32 },
+    /// A load command is smaller than its type requires or runs past `sizeofcmds`
+    BadCommandSize { index: u32, cmd: u32, cmdsize: u32 },
+    /// A path in a load command starts outside of the command
+    BadStringOffset {
+        index: u32,
+        offset: u32,
+        cmdsize: u32,
+    },
+    /// A load command refers to data outside of the slice
+    DataOutOfBounds {
+        index: u32,
+        cmd: u32,
+        offset: u64,
+        size: u64,
+    },
+    /// The patch file is malformed or doesn't belong to the binary
+    BadPatch(String),
+    /// The binary breaks this many structural rules, see `ops::check`
+    CheckFailed(usize),
+    /// A provenance record would be added after the code signature and invalidate it
+    SignedProvenance,
+}
+
+pub type Result<T, E = InsertDylibError> = std::result::Result<T, E>;
+
+impl InsertDylibError {
+    /// Process exit code for this error, 1 is left for usage errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InsertDylibError::Io(_) => 2,
+            InsertDylibError::BadMagic(_) => 3,
+            InsertDylibError::Truncated => 4,
+            InsertDylibError::NoSpace { .. } => 5,
+            InsertDylibError::AlreadyPresent(_) => 6,
+            InsertDylibError::SignatureNotLast => 7,
+            InsertDylibError::ArchMismatch(_) => 8,
+            InsertDylibError::DylibNotFound(_) => 9,
+            InsertDylibError::NotADylib => 10,
+            InsertDylibError::OrdinalsShifted(_) => 11,
+            InsertDylibError::Unsignable(_) => 13,
+            InsertDylibError::TooManyArchs(_)
+            | InsertDylibError::BadFatArch { .. }
+            | InsertDylibError::LoadCommandsOutOfBounds { .. }
+            | InsertDylibError::BadCommandSize { .. }
+            | InsertDylibError::BadStringOffset { .. }
+            | InsertDylibError::DataOutOfBounds { .. } => 12,
+            InsertDylibError::BadPatch(_) => 14,
+            InsertDylibError::CheckFailed(_) => 15,
+            InsertDylibError::SignedProvenance => 16,
+        }
+    }
+}
+
+impl fmt::Display for InsertDylibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertDylibError::Io(e) => write!(f, "{}", e),
+            InsertDylibError::BadMagic(magic) => {
+                write!(f, "Not a MachO binary, unknown magic {:08x}", magic)
+            }
+            InsertDylibError::Truncated => write!(f, "Binary is truncated"),
+            InsertDylibError::NoSpace { needed } => write!(
+                f,
+                "There isn't enough empty space after the load commands ({} bytes needed)",
+                needed
+            ),
+            InsertDylibError::AlreadyPresent(dylib_path) => write!(
+                f,
+                "Binary already contains a load command for {}",
+                dylib_path
+            ),
+            InsertDylibError::SignatureNotLast => write!(
+                f,
+                "LC_CODE_SIGNATURE is not the last load command, so couldn't remove"
+            ),
+            InsertDylibError::ArchMismatch(reason) => {
+                write!(f, "Architecture mismatch: {}", reason)
+            }
+            InsertDylibError::DylibNotFound(dylib_path) => write!(
+                f,
+                "Binary doesn't contain a load command for {}",
+                dylib_path
+            ),
+            InsertDylibError::NotADylib => {
+                write!(f, "Binary doesn't contain LC_ID_DYLIB, is it a dylib?")
+            }
+            InsertDylibError::OrdinalsShifted(shifted) => write!(
+                f,
+                "Removing the load commands changes the ordinals of {} dylib(s) loaded after them",
+                shifted
+            ),
+            InsertDylibError::Unsignable(reason) => write!(f, "Cannot sign the binary: {}", reason),
+            InsertDylibError::TooManyArchs(nfat_arch) => write!(
+                f,
+                "Fat header lists {} archs, more than fit in the file",
+                nfat_arch
+            ),
+            InsertDylibError::BadFatArch {
+                index,
+                offset,
+                size,
+                align,
+            } => write!(
+                f,
+                "Fat arch #{} is out of bounds (offset {:#x}, size {:#x}, align 2^{})",
+                index + 1,
+                offset,
+                size,
+                align
+            ),
+            InsertDylibError::LoadCommandsOutOfBounds { sizeofcmds } => write!(
+                f,
+                "Load commands ({} bytes) run past the end of the slice",
+                sizeofcmds
+            ),
+            InsertDylibError::BadCommandSize {
+                index,
+                cmd,
+                cmdsize,
+            } => write!(
+                f,
+                "Load command at {} ({}) has an invalid cmdsize {}",
+                index,
+                load_command_name(*cmd),
+                cmdsize
+            ),
+            InsertDylibError::BadStringOffset {
+                index,
+                offset,
+                cmdsize,
+            } => write!(
+                f,
+                "Path in load command at {} starts at offset {}, outside of the command ({} bytes)",
+                index, offset, cmdsize
+            ),
+            InsertDylibError::DataOutOfBounds {
+                index,
+                cmd,
+                offset,
+                size,
+            } => write!(
+                f,
+                "Load command at {} ({}) refers to data outside of the slice (offset {:#x}, size {:#x})",
+                index,
+                load_command_name(*cmd),
+                offset,
+                size
+            ),
+            InsertDylibError::BadPatch(reason) => write!(f, "Cannot use the patch: {}", reason),
+            InsertDylibError::CheckFailed(issues) => {
+                write!(f, "Binary breaks {} structural rule(s)", issues)
+            }
+            InsertDylibError::SignedProvenance => write!(
+                f,
+                "Binary is code signed, strip the signature before recording provenance"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InsertDylibError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InsertDylibError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for InsertDylibError {
+    fn from(e: io::Error) -> InsertDylibError {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => InsertDylibError::Truncated,
+            _ => InsertDylibError::Io(e),
+        }
+    }
+}
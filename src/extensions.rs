@@ -1,26 +1,37 @@
-use std::fs::File;
+use std::cmp::min;
 use std::io;
-use std::io::prelude::*;
 use std::io::SeekFrom;
 
-pub trait InsertDylibFileExt {
+use crate::macho::endian::{Endian, MachOStruct};
+use crate::storage::Storage;
+
+/// File-like operations used to patch a binary, available on every `Storage`
+pub trait InsertDylibFileExt: Storage {
     /// Read exactly `buf.len()` bytes and then rewind the file cursor `buf.len()` bytes
     fn fpeek(&mut self, buf: &mut [u8]) -> io::Result<()>;
+    /// Decode a `T` at the file cursor in the given byte order without moving the cursor
+    fn fpeek_struct<T: MachOStruct>(&mut self, endian: Endian) -> io::Result<T>;
     /// Overwrite `len` zeros to file starting from `offset` (from beginning)
     fn fbzero(&mut self, offset: u64, len: u64) -> io::Result<()>;
-    /// memmove alike, but on file
+    /// memmove alike, but on file, the ranges may overlap
     fn fmemmove(&mut self, dst: u64, src: u64, len: u64) -> io::Result<()>;
     /// Get current file cursor position
-    fn ftello(&mut self) -> u64;
+    fn ftello(&mut self) -> io::Result<u64>;
 }
 
-impl InsertDylibFileExt for File {
+impl<S: Storage> InsertDylibFileExt for S {
     fn fpeek(&mut self, buf: &mut [u8]) -> io::Result<()> {
         self.read_exact(buf)?;
         self.seek(SeekFrom::Current(0i64 - (buf.len() as i64)))?;
         Ok(())
     }
 
+    fn fpeek_struct<T: MachOStruct>(&mut self, endian: Endian) -> io::Result<T> {
+        let mut buf = vec![0u8; T::SIZE];
+        self.fpeek(&mut buf)?;
+        Ok(T::from_bytes(&buf, endian))
+    }
+
     fn fbzero(&mut self, offset: u64, len: u64) -> io::Result<()> {
         static ZEROS: [u8; 512] = [0u8; 512];
         self.seek(SeekFrom::Start(offset))?;
@@ -37,38 +48,113 @@ impl InsertDylibFileExt for File {
     }
 
     fn fmemmove(&mut self, dst: u64, src: u64, len: u64) -> io::Result<()> {
-        const BUFSIZE: u64 = 512;
-        let mut buf = [0u8; BUFSIZE as usize];
-        let mut remain = len;
-        let mut src = src;
-        let mut dst = dst;
-        while remain > 0 {
-            if remain < 512 {
-                self.seek(SeekFrom::Start(src))?;
-                let mut small_buf: Vec<u8> = vec![0; remain as usize];
-                self.read_exact(&mut small_buf)?;
-                self.seek(SeekFrom::Start(dst))?;
-                self.write_all(&small_buf)?;
-
-                remain -= remain;
-                src += remain;
-                dst += remain;
-            } else {
-                self.seek(SeekFrom::Start(src))?;
-                self.read_exact(&mut buf)?;
-                self.seek(SeekFrom::Start(dst))?;
-                self.write_all(&buf)?;
-
-                remain -= BUFSIZE;
-                src += BUFSIZE;
-                dst += BUFSIZE;
-            }
+        if dst == src || len == 0 || self.fcopy_range(dst, src, len)? {
+            return Ok(());
+        }
+
+        const BUFSIZE: u64 = 1 << 20;
+        let mut buf = vec![0u8; min(len, BUFSIZE) as usize];
+        // copy back to front if the destination overlaps the end of the source, so that every
+        // chunk is read before it is overwritten
+        let backward = dst > src && dst < src + len;
+        let mut copied = 0u64;
+        while copied < len {
+            let chunk = min(len - copied, BUFSIZE);
+            let chunk_start = match backward {
+                true => len - copied - chunk,
+                false => copied,
+            };
+            let buf = &mut buf[..chunk as usize];
+
+            self.seek(SeekFrom::Start(src + chunk_start))?;
+            self.read_exact(buf)?;
+            self.seek(SeekFrom::Start(dst + chunk_start))?;
+            self.write_all(buf)?;
+
+            copied += chunk;
         }
 
         Ok(())
     }
 
-    fn ftello(&mut self) -> u64 {
-        self.stream_position().unwrap()
+    fn ftello(&mut self) -> io::Result<u64> {
+        self.stream_position()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, Write};
+
+    use super::*;
+
+    /// A buffer without the `fcopy_range` fast path, so that `fmemmove` copies chunk by chunk
+    struct SlowBuffer(Cursor<Vec<u8>>);
+
+    impl Read for SlowBuffer {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for SlowBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Seek for SlowBuffer {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl Storage for SlowBuffer {
+        fn flen(&mut self) -> io::Result<u64> {
+            self.0.flen()
+        }
+
+        fn fset_len(&mut self, len: u64) -> io::Result<()> {
+            self.0.fset_len(len)
+        }
+    }
+
+    /// What `copy_within` does in memory is what `fmemmove` should do on a file
+    fn check_fmemmove(len: usize, dst: usize, src: usize, count: usize) {
+        let original: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let mut expected = original.clone();
+        expected.copy_within(src..src + count, dst);
+
+        let mut file = SlowBuffer(Cursor::new(original));
+        file.fmemmove(dst as u64, src as u64, count as u64).unwrap();
+        assert!(file.0.into_inner() == expected);
+    }
+
+    #[test]
+    fn fmemmove_overlapping_forward() {
+        check_fmemmove(64, 8, 0, 32);
+    }
+
+    #[test]
+    fn fmemmove_overlapping_backward() {
+        check_fmemmove(64, 0, 8, 32);
+    }
+
+    #[test]
+    fn fmemmove_overlapping_chunks() {
+        // more than one chunk, so that the order of the chunks matters too
+        let len = 3 << 20;
+        check_fmemmove(len, 100, 0, len - 100);
+        check_fmemmove(len, 0, 100, len - 100);
+    }
+
+    #[test]
+    fn fmemmove_disjoint() {
+        check_fmemmove(64, 40, 0, 16);
+        check_fmemmove(64, 0, 40, 16);
     }
 }
@@ -0,0 +1,387 @@
+//! The document printed by `--format json`
+//!
+//! Every document is an object with these keys:
+//!
+//! - `schema_version`: `SCHEMA_VERSION`, bumped whenever a key is removed or changes its meaning
+//! - `command`: the operation, e.g. `insert` or `list`, see `Operation::name`
+//! - `binary` and `output`: the input and output paths, `output` is null for read-only commands
+//! - `success`: whether the command succeeded
+//! - `slices`: one object per slice, either the outcome of the operation or what was read
+//! - `diff`: null, or what `--dry-run` would change or how `diff` found the binaries to differ,
+//!   see `binary_diff`
+//! - `warnings` and `messages`: everything that would have been printed in text mode, except for
+//!   the warnings about a slice a patching command reports in that slice's `warnings`
+//! - `error`: null, or the `code` the process exits with and a `message`
+
+use serde_json::{json, Value};
+
+use crate::arch::{format_version, platform_name, SliceArch};
+use crate::diff::{describe_command, BinaryDiff, CommandChange, FieldChange};
+use crate::error::InsertDylibError;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::ops::check::SliceLint;
+use crate::ops::dump::{CommandDetail, LoadCommandDump};
+use crate::ops::info::SliceInfo;
+use crate::ops::list::Dependencies;
+use crate::ops::provenance::Provenance;
+use crate::opts::Opts;
+use crate::report::{Collected, HeaderCounts, SliceReport};
+use crate::utils::{
+    filetype_name, format_source_version, format_uuid, get_segname, load_command_name, tool_name,
+};
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The whole document for a command, `slices` is only used by read-only commands, patching
+/// commands report theirs through `Reporter::slice`
+pub fn document(
+    options: &Opts,
+    slices: Vec<Value>,
+    diff: Option<Value>,
+    collected: Collected,
+    error: Option<&InsertDylibError>,
+) -> Value {
+    let modifies_binary = options.operation.modifies_binary();
+    let slices = match modifies_binary {
+        true => collected.slices.iter().map(slice_report).collect(),
+        false => slices,
+    };
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "command": options.operation.name(),
+        "binary": options.binary_path,
+        "output": match modifies_binary {
+            true => Some(&options.output_path),
+            false => None,
+        },
+        "success": error.is_none(),
+        "slices": slices,
+        "diff": diff,
+        "warnings": collected.warnings,
+        "messages": collected.messages,
+        "error": error.map(|e| json!({
+            "code": e.exit_code(),
+            "message": e.to_string(),
+        })),
+    })
+}
+
+fn header_counts(counts: &HeaderCounts) -> Value {
+    json!({
+        "ncmds": counts.ncmds,
+        "sizeofcmds": counts.sizeofcmds,
+    })
+}
+
+fn slice_report(report: &SliceReport) -> Value {
+    json!({
+        "arch": report.arch,
+        "offset": report.offset,
+        "before": header_counts(&report.before),
+        "after": report.after.as_ref().map(header_counts),
+        "error": report.error,
+        "warnings": report.warnings,
+    })
+}
+
+fn field_changes(changes: &[FieldChange]) -> Vec<Value> {
+    changes
+        .iter()
+        .map(|change| {
+            json!({
+                "field": change.name,
+                "before": change.before,
+                "after": change.after,
+            })
+        })
+        .collect()
+}
+
+/// The differences between two binaries, for `--dry-run` and `diff`
+pub fn binary_diff(diff: &BinaryDiff) -> Value {
+    let slices: Vec<Value> = diff
+        .slices
+        .iter()
+        .map(|slice| {
+            let status = match (slice.before, slice.after) {
+                (Some(_), None) => "removed",
+                (None, Some(_)) => "added",
+                _ if slice.is_empty() => "unchanged",
+                _ => "changed",
+            };
+            let commands: Vec<Value> = slice
+                .commands
+                .iter()
+                .map(|change| match change {
+                    CommandChange::Added(command) => json!({
+                        "change": "added",
+                        "command": describe_command(command),
+                        "index": command.index,
+                    }),
+                    CommandChange::Removed(command) => json!({
+                        "change": "removed",
+                        "command": describe_command(command),
+                        "index": command.index,
+                    }),
+                    CommandChange::Changed { after, fields, .. } => json!({
+                        "change": "changed",
+                        "command": describe_command(after),
+                        "index": after.index,
+                        "fields": field_changes(fields),
+                    }),
+                })
+                .collect();
+            json!({
+                "arch": slice.arch,
+                "status": status,
+                "header": field_changes(&slice.header),
+                "load_commands": commands,
+            })
+        })
+        .collect();
+    json!({
+        "fat": {
+            "before": diff.before.fat,
+            "after": diff.after.fat,
+        },
+        "file_length": {
+            "before": diff.before.len,
+            "after": diff.after.len,
+        },
+        "slices": slices,
+    })
+}
+
+fn build_version(arch: &SliceArch) -> Value {
+    match arch.build_version {
+        Some((platform, minos)) => json!({
+            "platform": platform_name(platform),
+            "minos": format_version(minos),
+        }),
+        None => Value::Null,
+    }
+}
+
+/// The dylibs and rpaths of a slice, for `list`
+pub fn dependencies(arch: &SliceArch, dependencies: &Dependencies) -> Value {
+    let dylibs: Vec<Value> = dependencies
+        .dylibs
+        .iter()
+        .map(|dylib| {
+            json!({
+                "cmd": load_command_name(dylib.cmd),
+                "path": dylib.path,
+                "current_version": format_version(dylib.current_version),
+                "compatibility_version": format_version(dylib.compatibility_version),
+            })
+        })
+        .collect();
+    json!({
+        "arch": arch.name(),
+        "dylibs": dylibs,
+        "rpaths": dependencies.rpaths,
+    })
+}
+
+/// The summary of a slice, for `info`
+pub fn slice_info(info: &SliceInfo) -> Value {
+    json!({
+        "arch": info.arch.name(),
+        "offset": info.range.offset,
+        "size": info.range.size,
+        "filetype": filetype_name(info.filetype),
+        "build_version": build_version(&info.arch),
+        "ncmds": info.ncmds,
+        "sizeofcmds": info.sizeofcmds,
+        "free_space": info.free_space,
+        "install_name": info.install_name,
+        "signed": info.signed,
+    })
+}
+
+/// The header and load commands of a slice, for `dump`
+pub fn load_commands(arch: &SliceArch, header: &MachHeader, commands: &[LoadCommandDump]) -> Value {
+    let commands: Vec<Value> = commands.iter().map(load_command).collect();
+    json!({
+        "arch": arch.name(),
+        "header": {
+            "magic": header.magic,
+            "cputype": header.cputype,
+            "cpusubtype": header.cpusubtype & !CPU_SUBTYPE_MASK,
+            "caps": header.cpusubtype >> 24,
+            "filetype": header.filetype,
+            "ncmds": header.ncmds,
+            "sizeofcmds": header.sizeofcmds,
+            "flags": header.flags,
+        },
+        "load_commands": commands,
+    })
+}
+
+/// The issues found in a slice, for `check`
+pub fn lint(slice: &SliceLint) -> Value {
+    let issues: Vec<Value> = slice
+        .issues
+        .iter()
+        .map(|issue| {
+            json!({
+                "rule": issue.rule,
+                "subject": issue.subject,
+                "message": issue.message,
+            })
+        })
+        .collect();
+    json!({
+        "arch": slice.arch,
+        "offset": slice.range.offset,
+        "size": slice.range.size,
+        "issues": issues,
+    })
+}
+
+/// The provenance records of a slice, for `history`
+pub fn provenance(arch: &SliceArch, records: &[Provenance]) -> Value {
+    let records: Vec<Value> = records.iter().map(Provenance::to_json).collect();
+    json!({
+        "arch": arch.name(),
+        "records": records,
+    })
+}
+
+fn load_command(command: &LoadCommandDump) -> Value {
+    let mut value = json!({
+        "index": command.index,
+        "offset": command.offset,
+        "cmd": load_command_name(command.cmd),
+        "cmdsize": command.cmdsize,
+    });
+    let fields = match &command.detail {
+        CommandDetail::Segment { command, sections } => {
+            let sections: Vec<Value> = sections.iter().map(section).collect();
+            json!({
+                "segname": get_segname(&command.segname),
+                "vmaddr": command.vmaddr,
+                "vmsize": command.vmsize,
+                "fileoff": command.fileoff,
+                "filesize": command.filesize,
+                "maxprot": command.maxprot,
+                "initprot": command.initprot,
+                "nsects": command.nsects,
+                "flags": command.flags,
+                "sections": sections,
+            })
+        }
+        CommandDetail::Symtab(command) => json!({
+            "symoff": command.symoff,
+            "nsyms": command.nsyms,
+            "stroff": command.stroff,
+            "strsize": command.strsize,
+        }),
+        CommandDetail::Dysymtab(command) => json!({
+            "ilocalsym": command.ilocalsym,
+            "nlocalsym": command.nlocalsym,
+            "iextdefsym": command.iextdefsym,
+            "nextdefsym": command.nextdefsym,
+            "iundefsym": command.iundefsym,
+            "nundefsym": command.nundefsym,
+            "tocoff": command.tocoff,
+            "ntoc": command.ntoc,
+            "modtaboff": command.modtaboff,
+            "nmodtab": command.nmodtab,
+            "extrefsymoff": command.extrefsymoff,
+            "nextrefsyms": command.nextrefsyms,
+            "indirectsymoff": command.indirectsymoff,
+            "nindirectsyms": command.nindirectsyms,
+            "extreloff": command.extreloff,
+            "nextrel": command.nextrel,
+            "locreloff": command.locreloff,
+            "nlocrel": command.nlocrel,
+        }),
+        CommandDetail::LinkeditData(command) => json!({
+            "dataoff": command.dataoff,
+            "datasize": command.datasize,
+        }),
+        CommandDetail::Dylib { command, path } => json!({
+            "name": path,
+            "name_offset": command.dylib.name_offset,
+            "timestamp": command.dylib.timestamp,
+            "current_version": format_version(command.dylib.current_version),
+            "compatibility_version": format_version(command.dylib.compatibility_version),
+        }),
+        CommandDetail::Dylinker(name) => json!({ "name": name }),
+        CommandDetail::Rpath(path) => json!({ "path": path }),
+        CommandDetail::Uuid(uuid) => json!({ "uuid": format_uuid(uuid) }),
+        CommandDetail::BuildVersion { command, tools } => {
+            let tools: Vec<Value> = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "tool": tool_name(tool.tool),
+                        "version": format_version(tool.version),
+                    })
+                })
+                .collect();
+            json!({
+                "platform": platform_name(command.platform),
+                "minos": format_version(command.minos),
+                "sdk": format_version(command.sdk),
+                "tools": tools,
+            })
+        }
+        CommandDetail::VersionMin(command) => json!({
+            "version": format_version(command.version),
+            "sdk": format_version(command.sdk),
+        }),
+        CommandDetail::SourceVersion(command) => json!({
+            "version": format_source_version(command.version),
+        }),
+        CommandDetail::EntryPoint(command) => json!({
+            "entryoff": command.entryoff,
+            "stacksize": command.stacksize,
+        }),
+        CommandDetail::EncryptionInfo(command) => json!({
+            "cryptoff": command.cryptoff,
+            "cryptsize": command.cryptsize,
+            "cryptid": command.cryptid,
+        }),
+        CommandDetail::DyldInfo(command) => json!({
+            "rebase_off": command.rebase_off,
+            "rebase_size": command.rebase_size,
+            "bind_off": command.bind_off,
+            "bind_size": command.bind_size,
+            "weak_bind_off": command.weak_bind_off,
+            "weak_bind_size": command.weak_bind_size,
+            "lazy_bind_off": command.lazy_bind_off,
+            "lazy_bind_size": command.lazy_bind_size,
+            "export_off": command.export_off,
+            "export_size": command.export_size,
+        }),
+        CommandDetail::Note(command) => json!({
+            "data_owner": get_segname(&command.data_owner),
+            "offset": command.offset,
+            "size": command.size,
+        }),
+        CommandDetail::Unknown => json!({}),
+    };
+    if let (Value::Object(value), Value::Object(fields)) = (&mut value, fields) {
+        value.extend(fields);
+    }
+    value
+}
+
+fn section(section: &Section64) -> Value {
+    json!({
+        "sectname": get_segname(&section.sectname),
+        "segname": get_segname(&section.segname),
+        "addr": section.addr,
+        "size": section.size,
+        "offset": section.offset,
+        "align": section.align,
+        "reloff": section.reloff,
+        "nreloc": section.nreloc,
+        "flags": section.flags,
+        "reserved1": section.reserved1,
+        "reserved2": section.reserved2,
+    })
+}
@@ -0,0 +1,16 @@
+pub mod arch;
+pub mod cli;
+pub mod diff;
+pub mod error;
+pub mod extensions;
+pub mod json;
+pub mod macho;
+pub mod ops;
+pub mod opts;
+pub mod output;
+pub mod patch;
+pub mod patchfile;
+pub mod record;
+pub mod report;
+pub mod storage;
+pub mod utils;
@@ -0,0 +1,204 @@
+use super::prelude::*;
+
+/// Byte order of a Mach-O file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Detect the byte order from the first 4 bytes of a Mach-O or fat header
+    pub fn from_magic(magic: [u8; 4]) -> Option<Endian> {
+        match u32::from_be_bytes(magic) {
+            MH_MAGIC | MH_MAGIC_64 | FAT_MAGIC => Some(Endian::Big),
+            MH_CIGAM | MH_CIGAM_64 | FAT_CIGAM => Some(Endian::Little),
+            _ => None,
+        }
+    }
+
+    pub fn read_u32(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    pub fn read_u64(self, bytes: [u8; 8]) -> u64 {
+        match self {
+            Endian::Little => u64::from_le_bytes(bytes),
+            Endian::Big => u64::from_be_bytes(bytes),
+        }
+    }
+
+    pub fn write_u32(self, value: u32) -> [u8; 4] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub fn write_u64(self, value: u64) -> [u8; 8] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+/// Sequentially decodes the fields of an on-disk struct
+pub struct FieldReader<'a> {
+    buffer: &'a [u8],
+    endian: Endian,
+}
+
+impl<'a> FieldReader<'a> {
+    pub fn new(buffer: &'a [u8], endian: Endian) -> FieldReader<'a> {
+        FieldReader { buffer, endian }
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.buffer.split_at(N);
+        self.buffer = rest;
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(field);
+        bytes
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.bytes::<1>()[0]
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        let bytes = self.bytes();
+        self.endian.read_u32(bytes)
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        let bytes = self.bytes();
+        self.endian.read_u64(bytes)
+    }
+}
+
+/// Sequentially encodes the fields of an on-disk struct
+pub struct FieldWriter {
+    buffer: Vec<u8>,
+    endian: Endian,
+}
+
+impl FieldWriter {
+    pub fn new(endian: Endian) -> FieldWriter {
+        FieldWriter {
+            buffer: Vec::new(),
+            endian,
+        }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&self.endian.write_u32(value));
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&self.endian.write_u64(value));
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// A fixed-size struct stored in a Mach-O file in the file's byte order
+pub trait MachOStruct: Sized {
+    /// Size of the struct on disk
+    const SIZE: usize;
+
+    fn read_fields(reader: &mut FieldReader) -> Self;
+
+    fn write_fields(&self, writer: &mut FieldWriter);
+
+    /// Decode the struct from the first `SIZE` bytes of `buffer`
+    ///
+    /// Panics if `buffer` is shorter than `SIZE`.
+    fn from_bytes(buffer: &[u8], endian: Endian) -> Self {
+        Self::read_fields(&mut FieldReader::new(&buffer[..Self::SIZE], endian))
+    }
+
+    /// Encode the struct in the given byte order
+    fn to_bytes(&self, endian: Endian) -> Vec<u8> {
+        let mut writer = FieldWriter::new(endian);
+        self.write_fields(&mut writer);
+        writer.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::macho::DylibCommand;
+
+    fn roundtrip(endian: Endian) -> Vec<u8> {
+        let mut writer = FieldWriter::new(endian);
+        writer.u8(0xab);
+        writer.u32(0x0102_0304);
+        writer.u64(0x0102_0304_0506_0708);
+        writer.bytes(b"ab");
+        let bytes = writer.into_bytes();
+
+        let mut reader = FieldReader::new(&bytes, endian);
+        assert_eq!(reader.u8(), 0xab);
+        assert_eq!(reader.u32(), 0x0102_0304);
+        assert_eq!(reader.u64(), 0x0102_0304_0506_0708);
+        assert_eq!(reader.bytes::<2>(), *b"ab");
+        bytes
+    }
+
+    #[test]
+    fn little_endian_roundtrip() {
+        assert_eq!(
+            roundtrip(Endian::Little),
+            [0xab, 4, 3, 2, 1, 8, 7, 6, 5, 4, 3, 2, 1, b'a', b'b']
+        );
+    }
+
+    #[test]
+    fn big_endian_roundtrip() {
+        assert_eq!(
+            roundtrip(Endian::Big),
+            [0xab, 1, 2, 3, 4, 1, 2, 3, 4, 5, 6, 7, 8, b'a', b'b']
+        );
+    }
+
+    #[test]
+    fn struct_roundtrip() {
+        for endian in [Endian::Little, Endian::Big] {
+            let bytes: Vec<u8> = (0..DylibCommand::SIZE as u8).collect();
+            let command = DylibCommand::from_bytes(&bytes, endian);
+            assert_eq!(command.to_bytes(endian), bytes);
+        }
+    }
+
+    #[test]
+    fn magic_byte_order() {
+        assert_eq!(
+            Endian::from_magic(MH_MAGIC_64.to_be_bytes()),
+            Some(Endian::Big)
+        );
+        assert_eq!(
+            Endian::from_magic(MH_MAGIC_64.to_le_bytes()),
+            Some(Endian::Little)
+        );
+        assert_eq!(
+            Endian::from_magic(FAT_MAGIC.to_be_bytes()),
+            Some(Endian::Big)
+        );
+        assert_eq!(Endian::from_magic([0; 4]), None);
+    }
+}
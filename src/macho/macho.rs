@@ -1,54 +1,29 @@
+use super::endian::*;
 use super::prelude::*;
 
-macro_rules! swap_bytes {
-    ($self:ident, $field_name:ident) => {
-        $self.$field_name = $self.$field_name.swap_bytes();
-    };
-}
-
-pub trait FixMachOStructEndian {
-    fn fix_endian(&mut self);
-}
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct FatHeader {
     pub magic: u32,
     pub nfat_arch: u32,
 }
 
-impl FatHeader {
-    pub fn from(buffer: [u8; 8], is_little_endian: bool) -> FatHeader {
-        let header_buffer: [u32; 2] =
-            unsafe { std::mem::transmute_copy::<[u8; 8], [u32; 2]>(&buffer) };
-        let mut fat_header = FatHeader {
-            magic: header_buffer[0],
-            nfat_arch: header_buffer[1],
-        };
+impl MachOStruct for FatHeader {
+    const SIZE: usize = 8;
 
-        if is_little_endian {
-            fat_header.fix_endian()
+    fn read_fields(reader: &mut FieldReader) -> FatHeader {
+        FatHeader {
+            magic: reader.u32(),
+            nfat_arch: reader.u32(),
         }
-
-        fat_header
     }
 
-    pub fn to_u8(&self) -> [u8; 8] {
-        let mut data: [u32; 2] = [0u32; 2];
-        data[0] = self.magic;
-        data[1] = self.nfat_arch;
-
-        unsafe { std::mem::transmute_copy::<[u32; 2], [u8; 8]>(&data) }
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.magic);
+        writer.u32(self.nfat_arch);
     }
 }
 
-impl FixMachOStructEndian for FatHeader {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, magic);
-        swap_bytes!(self, nfat_arch);
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct FatArch {
     pub cputype: u32,
     pub cpusubtype: u32,
@@ -57,48 +32,30 @@ pub struct FatArch {
     pub align: u32,
 }
 
-impl FatArch {
-    pub fn from(buffer: [u8; 20], is_little_endian: bool) -> FatArch {
-        let arch_buffer: [u32; 5] =
-            unsafe { std::mem::transmute_copy::<[u8; 20], [u32; 5]>(&buffer) };
-        let mut fat_arch = FatArch {
-            cputype: arch_buffer[0],
-            cpusubtype: arch_buffer[1],
-            offset: arch_buffer[2],
-            size: arch_buffer[3],
-            align: arch_buffer[4],
-        };
-
-        if is_little_endian {
-            fat_arch.fix_endian()
-        }
-
-        fat_arch
-    }
+impl MachOStruct for FatArch {
+    const SIZE: usize = 20;
 
-    pub fn to_u8(&self) -> [u8; 20] {
-        let mut data: [u32; 5] = [0u32; 5];
-        data[0] = self.cputype;
-        data[1] = self.cpusubtype;
-        data[2] = self.offset;
-        data[3] = self.size;
-        data[4] = self.align;
-
-        unsafe { std::mem::transmute_copy::<[u32; 5], [u8; 20]>(&data) }
+    fn read_fields(reader: &mut FieldReader) -> FatArch {
+        FatArch {
+            cputype: reader.u32(),
+            cpusubtype: reader.u32(),
+            offset: reader.u32(),
+            size: reader.u32(),
+            align: reader.u32(),
+        }
     }
-}
 
-impl FixMachOStructEndian for FatArch {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cputype);
-        swap_bytes!(self, cpusubtype);
-        swap_bytes!(self, offset);
-        swap_bytes!(self, size);
-        swap_bytes!(self, align);
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cputype);
+        writer.u32(self.cpusubtype);
+        writer.u32(self.offset);
+        writer.u32(self.size);
+        writer.u32(self.align);
     }
 }
 
-#[derive(Debug)]
+/// `mach_header` or `mach_header_64`, `reserved` is only present in the latter
+#[derive(Debug, Default)]
 pub struct MachHeader {
     pub magic: u32,
     pub cputype: u32,
@@ -111,73 +68,52 @@ pub struct MachHeader {
 }
 
 impl MachHeader {
-    pub fn from(buffer: [u8; 32]) -> MachHeader {
-        let header_buffer: [u32; 8] =
-            unsafe { std::mem::transmute_copy::<[u8; 32], [u32; 8]>(&buffer) };
-        let magic = header_buffer[0].swap_bytes();
-        let mut mach_header = MachHeader {
-            magic: header_buffer[0],
-            cputype: header_buffer[1],
-            cpusubtype: header_buffer[2],
-            filetype: header_buffer[3],
-            ncmds: header_buffer[4],
-            sizeofcmds: header_buffer[5],
-            flags: header_buffer[6],
-            reserved: header_buffer[7],
-        };
-
-        if !MachHeader::is_little_endian(magic) {
-            mach_header.fix_endian();
-        }
-
-        mach_header
+    pub fn is_64_bit(&self) -> bool {
+        self.magic == MH_MAGIC_64
     }
 
-    pub fn is_little_endian(magic: u32) -> bool {
-        match magic {
-            FAT_CIGAM | MH_CIGAM_64 | MH_CIGAM => true,
-            FAT_MAGIC | MH_MAGIC_64 | MH_MAGIC => false,
-            _ => panic!("Unknown MachO magic"),
+    /// Size of the header on disk
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        match self.is_64_bit() {
+            true => 32,
+            false => 28,
         }
     }
+}
 
-    pub fn to_u8(&self) -> [u8; 32] {
-        let mut data: [u32; 8] = [0u32; 8];
-        data[0] = self.magic;
-        data[1] = self.cputype;
-        data[2] = self.cpusubtype;
-        data[3] = self.filetype;
-        data[4] = self.ncmds;
-        data[5] = self.sizeofcmds;
-        data[6] = self.flags;
-        data[7] = self.reserved;
-
-        unsafe { std::mem::transmute_copy::<[u32; 8], [u8; 32]>(&data) }
-    }
-
-    pub fn len(&self) -> u64 {
-        match self.magic {
-            MH_MAGIC | MH_CIGAM => 28,
-            MH_MAGIC_64 | MH_CIGAM_64 => 32,
-            _ => panic!("Unknown MachO magic"),
+impl MachOStruct for MachHeader {
+    /// Size of `mach_header_64`, a 32-bit header only uses the first 28 bytes
+    const SIZE: usize = 32;
+
+    fn read_fields(reader: &mut FieldReader) -> MachHeader {
+        MachHeader {
+            magic: reader.u32(),
+            cputype: reader.u32(),
+            cpusubtype: reader.u32(),
+            filetype: reader.u32(),
+            ncmds: reader.u32(),
+            sizeofcmds: reader.u32(),
+            flags: reader.u32(),
+            reserved: reader.u32(),
         }
     }
-}
 
-impl FixMachOStructEndian for MachHeader {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, magic);
-        swap_bytes!(self, cputype);
-        swap_bytes!(self, cpusubtype);
-        swap_bytes!(self, filetype);
-        swap_bytes!(self, ncmds);
-        swap_bytes!(self, sizeofcmds);
-        swap_bytes!(self, flags);
-        swap_bytes!(self, reserved);
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.magic);
+        writer.u32(self.cputype);
+        writer.u32(self.cpusubtype);
+        writer.u32(self.filetype);
+        writer.u32(self.ncmds);
+        writer.u32(self.sizeofcmds);
+        writer.u32(self.flags);
+        if self.is_64_bit() {
+            writer.u32(self.reserved);
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct SegmentCommand {
     pub cmd: u32,
     pub cmdsize: u32,
@@ -192,85 +128,41 @@ pub struct SegmentCommand {
     pub flags: u32,
 }
 
-impl SegmentCommand {
-    pub fn default() -> SegmentCommand {
+impl MachOStruct for SegmentCommand {
+    const SIZE: usize = 56;
+
+    fn read_fields(reader: &mut FieldReader) -> SegmentCommand {
         SegmentCommand {
-            cmd: 0,
-            cmdsize: 0,
-            segname: [0u8; 16],
-            vmaddr: 0,
-            vmsize: 0,
-            fileoff: 0,
-            filesize: 0,
-            maxprot: 0,
-            initprot: 0,
-            nsects: 0,
-            flags: 0,
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            segname: reader.bytes(),
+            vmaddr: reader.u32(),
+            vmsize: reader.u32(),
+            fileoff: reader.u32(),
+            filesize: reader.u32(),
+            maxprot: reader.u32(),
+            initprot: reader.u32(),
+            nsects: reader.u32(),
+            flags: reader.u32(),
         }
     }
 
-    pub fn from(buffer: [u8; 56], is_little_endian: bool) -> SegmentCommand {
-        let sc_buffer: [u32; 14] =
-            unsafe { std::mem::transmute_copy::<[u8; 56], [u32; 14]>(&buffer) };
-        let mut segment_command = SegmentCommand::default();
-        segment_command.cmd = sc_buffer[0];
-        segment_command.cmdsize = sc_buffer[1];
-        segment_command.segname[..16usize].copy_from_slice(&buffer[8..(16usize + 8)]);
-        segment_command.vmaddr = sc_buffer[6];
-        segment_command.vmsize = sc_buffer[7];
-        segment_command.fileoff = sc_buffer[8];
-        segment_command.filesize = sc_buffer[9];
-        segment_command.maxprot = sc_buffer[10];
-        segment_command.initprot = sc_buffer[11];
-        segment_command.nsects = sc_buffer[12];
-        segment_command.flags = sc_buffer[13];
-
-        if is_little_endian {
-            segment_command.fix_endian();
-        }
-
-        segment_command
-    }
-
-    pub fn to_u8(&self) -> [u8; 56] {
-        let mut data: [u32; 14] = [0u32; 14];
-        let segname_data: [u32; 4] =
-            unsafe { std::mem::transmute_copy::<[u8; 16], [u32; 4]>(&self.segname) };
-        data[0] = self.cmd;
-        data[1] = self.cmdsize;
-        data[2] = segname_data[0];
-        data[3] = segname_data[1];
-        data[4] = segname_data[2];
-        data[5] = segname_data[3];
-        data[6] = self.vmaddr;
-        data[7] = self.vmsize;
-        data[8] = self.fileoff;
-        data[9] = self.filesize;
-        data[10] = self.maxprot;
-        data[11] = self.initprot;
-        data[12] = self.nsects;
-        data[13] = self.flags;
-
-        unsafe { std::mem::transmute_copy::<[u32; 14], [u8; 56]>(&data) }
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.bytes(&self.segname);
+        writer.u32(self.vmaddr);
+        writer.u32(self.vmsize);
+        writer.u32(self.fileoff);
+        writer.u32(self.filesize);
+        writer.u32(self.maxprot);
+        writer.u32(self.initprot);
+        writer.u32(self.nsects);
+        writer.u32(self.flags);
     }
 }
 
-impl FixMachOStructEndian for SegmentCommand {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cmd);
-        swap_bytes!(self, cmdsize);
-        swap_bytes!(self, vmaddr);
-        swap_bytes!(self, vmsize);
-        swap_bytes!(self, fileoff);
-        swap_bytes!(self, filesize);
-        swap_bytes!(self, maxprot);
-        swap_bytes!(self, initprot);
-        swap_bytes!(self, nsects);
-        swap_bytes!(self, flags);
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct SegmentCommand64 {
     pub cmd: u32,
     pub cmdsize: u32,
@@ -285,124 +177,63 @@ pub struct SegmentCommand64 {
     pub flags: u32,
 }
 
-impl SegmentCommand64 {
-    pub fn default() -> SegmentCommand64 {
+impl MachOStruct for SegmentCommand64 {
+    const SIZE: usize = 72;
+
+    fn read_fields(reader: &mut FieldReader) -> SegmentCommand64 {
         SegmentCommand64 {
-            cmd: 0,
-            cmdsize: 0,
-            segname: [0u8; 16],
-            vmaddr: 0,
-            vmsize: 0,
-            fileoff: 0,
-            filesize: 0,
-            maxprot: 0,
-            initprot: 0,
-            nsects: 0,
-            flags: 0,
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            segname: reader.bytes(),
+            vmaddr: reader.u64(),
+            vmsize: reader.u64(),
+            fileoff: reader.u64(),
+            filesize: reader.u64(),
+            maxprot: reader.u32(),
+            initprot: reader.u32(),
+            nsects: reader.u32(),
+            flags: reader.u32(),
         }
     }
 
-    pub fn from(buffer: [u8; 72], is_little_endian: bool) -> SegmentCommand64 {
-        let sc_buffer: [u32; 18] =
-            unsafe { std::mem::transmute_copy::<[u8; 72], [u32; 18]>(&buffer) };
-        let mut segment_command = SegmentCommand64::default();
-        segment_command.cmd = sc_buffer[0];
-        segment_command.cmdsize = sc_buffer[1];
-        segment_command.segname[..16usize].copy_from_slice(&buffer[8..(16usize + 8)]);
-        segment_command.vmaddr = ((sc_buffer[7] as u64) << 32) + (sc_buffer[6] as u64);
-        segment_command.vmsize = ((sc_buffer[9] as u64) << 32) + (sc_buffer[8] as u64);
-        segment_command.fileoff = ((sc_buffer[11] as u64) << 32) + (sc_buffer[10] as u64);
-        segment_command.filesize = ((sc_buffer[13] as u64) << 32) + (sc_buffer[12] as u64);
-        segment_command.maxprot = sc_buffer[14];
-        segment_command.initprot = sc_buffer[15];
-        segment_command.nsects = sc_buffer[16];
-        segment_command.flags = sc_buffer[17];
-
-        if is_little_endian {
-            segment_command.fix_endian();
-        }
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.bytes(&self.segname);
+        writer.u64(self.vmaddr);
+        writer.u64(self.vmsize);
+        writer.u64(self.fileoff);
+        writer.u64(self.filesize);
+        writer.u32(self.maxprot);
+        writer.u32(self.initprot);
+        writer.u32(self.nsects);
+        writer.u32(self.flags);
+    }
+}
 
-        segment_command
-    }
-
-    pub fn to_u8(&self) -> [u8; 72] {
-        let mut data: [u32; 18] = [0u32; 18];
-        let segname_data: [u32; 4] =
-            unsafe { std::mem::transmute_copy::<[u8; 16], [u32; 4]>(&self.segname) };
-        data[0] = self.cmd;
-        data[1] = self.cmdsize;
-        data[2] = segname_data[0];
-        data[3] = segname_data[1];
-        data[4] = segname_data[2];
-        data[5] = segname_data[3];
-
-        let vmaddr_data: [u32; 2] = unsafe { std::mem::transmute_copy(&self.vmaddr) };
-        data[6] = vmaddr_data[0];
-        data[7] = vmaddr_data[1];
-        let vmsize_data: [u32; 2] = unsafe { std::mem::transmute_copy(&self.vmsize) };
-        data[8] = vmsize_data[0];
-        data[9] = vmsize_data[1];
-        let fileoff_data: [u32; 2] = unsafe { std::mem::transmute_copy(&self.fileoff) };
-        data[10] = fileoff_data[0];
-        data[11] = fileoff_data[1];
-        let filesize_data: [u32; 2] = unsafe { std::mem::transmute_copy(&self.filesize) };
-        data[12] = filesize_data[0];
-        data[13] = filesize_data[1];
-
-        data[14] = self.maxprot;
-        data[15] = self.initprot;
-        data[16] = self.nsects;
-        data[17] = self.flags;
-
-        unsafe { std::mem::transmute_copy::<[u32; 18], [u8; 72]>(&data) }
-    }
-}
-
-impl FixMachOStructEndian for SegmentCommand64 {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cmd);
-        swap_bytes!(self, cmdsize);
-        swap_bytes!(self, vmaddr);
-        swap_bytes!(self, vmsize);
-        swap_bytes!(self, fileoff);
-        swap_bytes!(self, filesize);
-        swap_bytes!(self, maxprot);
-        swap_bytes!(self, initprot);
-        swap_bytes!(self, nsects);
-        swap_bytes!(self, flags);
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct LoadCommand {
     pub cmd: u32,
     pub cmdsize: u32,
 }
 
-impl LoadCommand {
-    pub fn from(buffer: [u8; 8], is_little_endian: bool) -> LoadCommand {
-        let lc_buffer: [u32; 2] = unsafe { std::mem::transmute_copy::<[u8; 8], [u32; 2]>(&buffer) };
-        let mut load_command = LoadCommand {
-            cmd: lc_buffer[0],
-            cmdsize: lc_buffer[1],
-        };
+impl MachOStruct for LoadCommand {
+    const SIZE: usize = 8;
 
-        if is_little_endian {
-            load_command.fix_endian();
+    fn read_fields(reader: &mut FieldReader) -> LoadCommand {
+        LoadCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
         }
-
-        load_command
     }
-}
 
-impl FixMachOStructEndian for LoadCommand {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cmd);
-        swap_bytes!(self, cmdsize);
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct LinkeditDataCommand {
     pub cmd: u32,
     pub cmdsize: u32,
@@ -410,35 +241,27 @@ pub struct LinkeditDataCommand {
     pub datasize: u32,
 }
 
-impl LinkeditDataCommand {
-    pub fn from(buffer: [u8; 16], is_little_endian: bool) -> LinkeditDataCommand {
-        let ldc_buffer: [u32; 4] =
-            unsafe { std::mem::transmute_copy::<[u8; 16], [u32; 4]>(&buffer) };
-        let mut linkedit_data_command = LinkeditDataCommand {
-            cmd: ldc_buffer[0],
-            cmdsize: ldc_buffer[1],
-            dataoff: ldc_buffer[2],
-            datasize: ldc_buffer[3],
-        };
+impl MachOStruct for LinkeditDataCommand {
+    const SIZE: usize = 16;
 
-        if is_little_endian {
-            linkedit_data_command.fix_endian();
+    fn read_fields(reader: &mut FieldReader) -> LinkeditDataCommand {
+        LinkeditDataCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            dataoff: reader.u32(),
+            datasize: reader.u32(),
         }
-
-        linkedit_data_command
     }
-}
 
-impl FixMachOStructEndian for LinkeditDataCommand {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cmd);
-        swap_bytes!(self, cmdsize);
-        swap_bytes!(self, dataoff);
-        swap_bytes!(self, datasize);
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.dataoff);
+        writer.u32(self.datasize);
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Dylib {
     pub name_offset: u32,
     pub timestamp: u32,
@@ -446,132 +269,708 @@ pub struct Dylib {
     pub compatibility_version: u32,
 }
 
-impl FixMachOStructEndian for Dylib {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, name_offset);
-        swap_bytes!(self, timestamp);
-        swap_bytes!(self, current_version);
-        swap_bytes!(self, compatibility_version);
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct DylibCommand {
     pub cmd: u32,
     pub cmdsize: u32,
     pub dylib: Dylib,
 }
 
-impl DylibCommand {
-    pub fn default() -> DylibCommand {
+impl MachOStruct for DylibCommand {
+    const SIZE: usize = 24;
+
+    fn read_fields(reader: &mut FieldReader) -> DylibCommand {
         DylibCommand {
-            cmd: 0,
-            cmdsize: 0,
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
             dylib: Dylib {
-                name_offset: 0,
-                timestamp: 0,
-                current_version: 0,
-                compatibility_version: 0,
+                name_offset: reader.u32(),
+                timestamp: reader.u32(),
+                current_version: reader.u32(),
+                compatibility_version: reader.u32(),
             },
         }
     }
 
-    pub fn from(buffer: [u8; 24], is_little_endian: bool) -> DylibCommand {
-        let dc_buffer: [u32; 6] =
-            unsafe { std::mem::transmute_copy::<[u8; 24], [u32; 6]>(&buffer) };
-        let mut dylib_command = DylibCommand {
-            cmd: dc_buffer[0],
-            cmdsize: dc_buffer[1],
-            dylib: Dylib {
-                name_offset: dc_buffer[2],
-                timestamp: dc_buffer[3],
-                current_version: dc_buffer[4],
-                compatibility_version: dc_buffer[5],
-            },
-        };
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.dylib.name_offset);
+        writer.u32(self.dylib.timestamp);
+        writer.u32(self.dylib.current_version);
+        writer.u32(self.dylib.compatibility_version);
+    }
+}
 
-        if is_little_endian {
-            dylib_command.fix_endian();
+#[derive(Debug, Default)]
+pub struct SymtabCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub symoff: u32,
+    pub nsyms: u32,
+    pub stroff: u32,
+    pub strsize: u32,
+}
+
+impl MachOStruct for SymtabCommand {
+    const SIZE: usize = 24;
+
+    fn read_fields(reader: &mut FieldReader) -> SymtabCommand {
+        SymtabCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            symoff: reader.u32(),
+            nsyms: reader.u32(),
+            stroff: reader.u32(),
+            strsize: reader.u32(),
         }
+    }
 
-        dylib_command
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.symoff);
+        writer.u32(self.nsyms);
+        writer.u32(self.stroff);
+        writer.u32(self.strsize);
     }
+}
 
-    pub fn to_u8(&self) -> [u8; 24] {
-        let mut data: [u32; 6] = [0u32; 6];
-        data[0] = self.cmd;
-        data[1] = self.cmdsize;
-        data[2] = self.dylib.name_offset;
-        data[3] = self.dylib.timestamp;
-        data[4] = self.dylib.current_version;
-        data[5] = self.dylib.compatibility_version;
+#[derive(Debug, Default)]
+pub struct BuildVersionCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub platform: u32,
+    pub minos: u32,
+    pub sdk: u32,
+    pub ntools: u32,
+}
 
-        unsafe { std::mem::transmute_copy::<[u32; 6], [u8; 24]>(&data) }
+impl MachOStruct for BuildVersionCommand {
+    const SIZE: usize = 24;
+
+    fn read_fields(reader: &mut FieldReader) -> BuildVersionCommand {
+        BuildVersionCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            platform: reader.u32(),
+            minos: reader.u32(),
+            sdk: reader.u32(),
+            ntools: reader.u32(),
+        }
     }
 
-    pub fn len() -> u64 {
-        24
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.platform);
+        writer.u32(self.minos);
+        writer.u32(self.sdk);
+        writer.u32(self.ntools);
     }
 }
 
-impl FixMachOStructEndian for DylibCommand {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cmd);
-        swap_bytes!(self, cmdsize);
-        self.dylib.fix_endian();
+#[derive(Debug, Default)]
+pub struct RpathCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub path_offset: u32,
+}
+
+impl MachOStruct for RpathCommand {
+    const SIZE: usize = 12;
+
+    fn read_fields(reader: &mut FieldReader) -> RpathCommand {
+        RpathCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            path_offset: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.path_offset);
     }
 }
 
-#[derive(Debug)]
-pub struct SymtabCommand {
+/// `section`, follows its LC_SEGMENT
+#[derive(Debug, Default)]
+pub struct Section {
+    pub sectname: [u8; 16],
+    pub segname: [u8; 16],
+    pub addr: u32,
+    pub size: u32,
+    pub offset: u32,
+    pub align: u32,
+    pub reloff: u32,
+    pub nreloc: u32,
+    pub flags: u32,
+    pub reserved1: u32,
+    pub reserved2: u32,
+}
+
+impl MachOStruct for Section {
+    const SIZE: usize = 68;
+
+    fn read_fields(reader: &mut FieldReader) -> Section {
+        Section {
+            sectname: reader.bytes(),
+            segname: reader.bytes(),
+            addr: reader.u32(),
+            size: reader.u32(),
+            offset: reader.u32(),
+            align: reader.u32(),
+            reloff: reader.u32(),
+            nreloc: reader.u32(),
+            flags: reader.u32(),
+            reserved1: reader.u32(),
+            reserved2: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.bytes(&self.sectname);
+        writer.bytes(&self.segname);
+        writer.u32(self.addr);
+        writer.u32(self.size);
+        writer.u32(self.offset);
+        writer.u32(self.align);
+        writer.u32(self.reloff);
+        writer.u32(self.nreloc);
+        writer.u32(self.flags);
+        writer.u32(self.reserved1);
+        writer.u32(self.reserved2);
+    }
+}
+
+/// `section_64`, follows its LC_SEGMENT_64
+#[derive(Debug, Default)]
+pub struct Section64 {
+    pub sectname: [u8; 16],
+    pub segname: [u8; 16],
+    pub addr: u64,
+    pub size: u64,
+    pub offset: u32,
+    pub align: u32,
+    pub reloff: u32,
+    pub nreloc: u32,
+    pub flags: u32,
+    pub reserved1: u32,
+    pub reserved2: u32,
+    pub reserved3: u32,
+}
+
+impl MachOStruct for Section64 {
+    const SIZE: usize = 80;
+
+    fn read_fields(reader: &mut FieldReader) -> Section64 {
+        Section64 {
+            sectname: reader.bytes(),
+            segname: reader.bytes(),
+            addr: reader.u64(),
+            size: reader.u64(),
+            offset: reader.u32(),
+            align: reader.u32(),
+            reloff: reader.u32(),
+            nreloc: reader.u32(),
+            flags: reader.u32(),
+            reserved1: reader.u32(),
+            reserved2: reader.u32(),
+            reserved3: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.bytes(&self.sectname);
+        writer.bytes(&self.segname);
+        writer.u64(self.addr);
+        writer.u64(self.size);
+        writer.u32(self.offset);
+        writer.u32(self.align);
+        writer.u32(self.reloff);
+        writer.u32(self.nreloc);
+        writer.u32(self.flags);
+        writer.u32(self.reserved1);
+        writer.u32(self.reserved2);
+        writer.u32(self.reserved3);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DysymtabCommand {
     pub cmd: u32,
     pub cmdsize: u32,
-    pub symoff: u32,
-    pub nsyms: u32,
-    pub stroff: u32,
-    pub strsize: u32,
+    pub ilocalsym: u32,
+    pub nlocalsym: u32,
+    pub iextdefsym: u32,
+    pub nextdefsym: u32,
+    pub iundefsym: u32,
+    pub nundefsym: u32,
+    pub tocoff: u32,
+    pub ntoc: u32,
+    pub modtaboff: u32,
+    pub nmodtab: u32,
+    pub extrefsymoff: u32,
+    pub nextrefsyms: u32,
+    pub indirectsymoff: u32,
+    pub nindirectsyms: u32,
+    pub extreloff: u32,
+    pub nextrel: u32,
+    pub locreloff: u32,
+    pub nlocrel: u32,
 }
 
-impl SymtabCommand {
-    pub fn from(buffer: [u8; 24], is_little_endian: bool) -> SymtabCommand {
-        let sc_buffer: [u32; 6] =
-            unsafe { std::mem::transmute_copy::<[u8; 24], [u32; 6]>(&buffer) };
-        let mut symtab_command = SymtabCommand {
-            cmd: sc_buffer[0],
-            cmdsize: sc_buffer[1],
-            symoff: sc_buffer[2],
-            nsyms: sc_buffer[3],
-            stroff: sc_buffer[4],
-            strsize: sc_buffer[5],
-        };
-
-        if is_little_endian {
-            symtab_command.fix_endian();
+impl MachOStruct for DysymtabCommand {
+    const SIZE: usize = 80;
+
+    fn read_fields(reader: &mut FieldReader) -> DysymtabCommand {
+        DysymtabCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            ilocalsym: reader.u32(),
+            nlocalsym: reader.u32(),
+            iextdefsym: reader.u32(),
+            nextdefsym: reader.u32(),
+            iundefsym: reader.u32(),
+            nundefsym: reader.u32(),
+            tocoff: reader.u32(),
+            ntoc: reader.u32(),
+            modtaboff: reader.u32(),
+            nmodtab: reader.u32(),
+            extrefsymoff: reader.u32(),
+            nextrefsyms: reader.u32(),
+            indirectsymoff: reader.u32(),
+            nindirectsyms: reader.u32(),
+            extreloff: reader.u32(),
+            nextrel: reader.u32(),
+            locreloff: reader.u32(),
+            nlocrel: reader.u32(),
         }
+    }
 
-        symtab_command
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.ilocalsym);
+        writer.u32(self.nlocalsym);
+        writer.u32(self.iextdefsym);
+        writer.u32(self.nextdefsym);
+        writer.u32(self.iundefsym);
+        writer.u32(self.nundefsym);
+        writer.u32(self.tocoff);
+        writer.u32(self.ntoc);
+        writer.u32(self.modtaboff);
+        writer.u32(self.nmodtab);
+        writer.u32(self.extrefsymoff);
+        writer.u32(self.nextrefsyms);
+        writer.u32(self.indirectsymoff);
+        writer.u32(self.nindirectsyms);
+        writer.u32(self.extreloff);
+        writer.u32(self.nextrel);
+        writer.u32(self.locreloff);
+        writer.u32(self.nlocrel);
     }
+}
+
+#[derive(Debug, Default)]
+pub struct UuidCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub uuid: [u8; 16],
+}
 
-    pub fn to_u8(&self) -> [u8; 24] {
-        let mut data: [u32; 6] = [0u32; 6];
-        data[0] = self.cmd;
-        data[1] = self.cmdsize;
-        data[2] = self.symoff;
-        data[3] = self.nsyms;
-        data[4] = self.stroff;
-        data[5] = self.strsize;
+impl MachOStruct for UuidCommand {
+    const SIZE: usize = 24;
 
-        unsafe { std::mem::transmute_copy::<[u32; 6], [u8; 24]>(&data) }
+    fn read_fields(reader: &mut FieldReader) -> UuidCommand {
+        UuidCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            uuid: reader.bytes(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.bytes(&self.uuid);
     }
 }
 
-impl FixMachOStructEndian for SymtabCommand {
-    fn fix_endian(&mut self) {
-        swap_bytes!(self, cmd);
-        swap_bytes!(self, cmdsize);
-        swap_bytes!(self, symoff);
-        swap_bytes!(self, nsyms);
-        swap_bytes!(self, stroff);
-        swap_bytes!(self, strsize);
+/// LC_MAIN
+#[derive(Debug, Default)]
+pub struct EntryPointCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub entryoff: u64,
+    pub stacksize: u64,
+}
+
+impl MachOStruct for EntryPointCommand {
+    const SIZE: usize = 24;
+
+    fn read_fields(reader: &mut FieldReader) -> EntryPointCommand {
+        EntryPointCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            entryoff: reader.u64(),
+            stacksize: reader.u64(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u64(self.entryoff);
+        writer.u64(self.stacksize);
+    }
+}
+
+/// LC_ENCRYPTION_INFO, or LC_ENCRYPTION_INFO_64 without its trailing `pad`
+#[derive(Debug, Default)]
+pub struct EncryptionInfoCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub cryptoff: u32,
+    pub cryptsize: u32,
+    pub cryptid: u32,
+}
+
+impl MachOStruct for EncryptionInfoCommand {
+    const SIZE: usize = 20;
+
+    fn read_fields(reader: &mut FieldReader) -> EncryptionInfoCommand {
+        EncryptionInfoCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            cryptoff: reader.u32(),
+            cryptsize: reader.u32(),
+            cryptid: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.cryptoff);
+        writer.u32(self.cryptsize);
+        writer.u32(self.cryptid);
+    }
+}
+
+/// Follows LC_BUILD_VERSION `ntools` times
+#[derive(Debug, Default)]
+pub struct BuildToolVersion {
+    pub tool: u32,
+    pub version: u32,
+}
+
+impl MachOStruct for BuildToolVersion {
+    const SIZE: usize = 8;
+
+    fn read_fields(reader: &mut FieldReader) -> BuildToolVersion {
+        BuildToolVersion {
+            tool: reader.u32(),
+            version: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.tool);
+        writer.u32(self.version);
+    }
+}
+
+/// LC_VERSION_MIN_*
+#[derive(Debug, Default)]
+pub struct VersionMinCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub version: u32,
+    pub sdk: u32,
+}
+
+impl MachOStruct for VersionMinCommand {
+    const SIZE: usize = 16;
+
+    fn read_fields(reader: &mut FieldReader) -> VersionMinCommand {
+        VersionMinCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            version: reader.u32(),
+            sdk: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.version);
+        writer.u32(self.sdk);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SourceVersionCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub version: u64,
+}
+
+impl MachOStruct for SourceVersionCommand {
+    const SIZE: usize = 16;
+
+    fn read_fields(reader: &mut FieldReader) -> SourceVersionCommand {
+        SourceVersionCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            version: reader.u64(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u64(self.version);
+    }
+}
+
+/// LC_DYLD_INFO and LC_DYLD_INFO_ONLY
+#[derive(Debug, Default)]
+pub struct DyldInfoCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub rebase_off: u32,
+    pub rebase_size: u32,
+    pub bind_off: u32,
+    pub bind_size: u32,
+    pub weak_bind_off: u32,
+    pub weak_bind_size: u32,
+    pub lazy_bind_off: u32,
+    pub lazy_bind_size: u32,
+    pub export_off: u32,
+    pub export_size: u32,
+}
+
+impl MachOStruct for DyldInfoCommand {
+    const SIZE: usize = 48;
+
+    fn read_fields(reader: &mut FieldReader) -> DyldInfoCommand {
+        DyldInfoCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            rebase_off: reader.u32(),
+            rebase_size: reader.u32(),
+            bind_off: reader.u32(),
+            bind_size: reader.u32(),
+            weak_bind_off: reader.u32(),
+            weak_bind_size: reader.u32(),
+            lazy_bind_off: reader.u32(),
+            lazy_bind_size: reader.u32(),
+            export_off: reader.u32(),
+            export_size: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.rebase_off);
+        writer.u32(self.rebase_size);
+        writer.u32(self.bind_off);
+        writer.u32(self.bind_size);
+        writer.u32(self.weak_bind_off);
+        writer.u32(self.weak_bind_size);
+        writer.u32(self.lazy_bind_off);
+        writer.u32(self.lazy_bind_size);
+        writer.u32(self.export_off);
+        writer.u32(self.export_size);
+    }
+}
+
+/// LC_LOAD_DYLINKER, LC_ID_DYLINKER and LC_DYLD_ENVIRONMENT
+#[derive(Debug, Default)]
+pub struct DylinkerCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub name_offset: u32,
+}
+
+impl MachOStruct for DylinkerCommand {
+    const SIZE: usize = 12;
+
+    fn read_fields(reader: &mut FieldReader) -> DylinkerCommand {
+        DylinkerCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            name_offset: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.u32(self.name_offset);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NoteCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub data_owner: [u8; 16],
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl MachOStruct for NoteCommand {
+    const SIZE: usize = 40;
+
+    fn read_fields(reader: &mut FieldReader) -> NoteCommand {
+        NoteCommand {
+            cmd: reader.u32(),
+            cmdsize: reader.u32(),
+            data_owner: reader.bytes(),
+            offset: reader.u64(),
+            size: reader.u64(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.cmd);
+        writer.u32(self.cmdsize);
+        writer.bytes(&self.data_owner);
+        writer.u64(self.offset);
+        writer.u64(self.size);
+    }
+}
+
+/// Header of the embedded signature blob, always big endian, followed by `count` `BlobIndex`es
+#[derive(Debug, Default)]
+pub struct SuperBlob {
+    pub magic: u32,
+    pub length: u32,
+    pub count: u32,
+}
+
+impl MachOStruct for SuperBlob {
+    const SIZE: usize = 12;
+
+    fn read_fields(reader: &mut FieldReader) -> SuperBlob {
+        SuperBlob {
+            magic: reader.u32(),
+            length: reader.u32(),
+            count: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.magic);
+        writer.u32(self.length);
+        writer.u32(self.count);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BlobIndex {
+    pub blob_type: u32,
+    pub offset: u32,
+}
+
+impl MachOStruct for BlobIndex {
+    const SIZE: usize = 8;
+
+    fn read_fields(reader: &mut FieldReader) -> BlobIndex {
+        BlobIndex {
+            blob_type: reader.u32(),
+            offset: reader.u32(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.blob_type);
+        writer.u32(self.offset);
+    }
+}
+
+/// Version 0x20400 `CS_CodeDirectory`, always big endian
+#[derive(Debug, Default)]
+pub struct CodeDirectory {
+    pub magic: u32,
+    pub length: u32,
+    pub version: u32,
+    pub flags: u32,
+    pub hash_offset: u32,
+    pub ident_offset: u32,
+    pub n_special_slots: u32,
+    pub n_code_slots: u32,
+    pub code_limit: u32,
+    pub hash_size: u8,
+    pub hash_type: u8,
+    pub platform: u8,
+    pub page_size: u8,
+    pub spare2: u32,
+    pub scatter_offset: u32,
+    pub team_offset: u32,
+    pub spare3: u32,
+    pub code_limit_64: u64,
+    pub exec_seg_base: u64,
+    pub exec_seg_limit: u64,
+    pub exec_seg_flags: u64,
+}
+
+impl MachOStruct for CodeDirectory {
+    const SIZE: usize = 88;
+
+    fn read_fields(reader: &mut FieldReader) -> CodeDirectory {
+        CodeDirectory {
+            magic: reader.u32(),
+            length: reader.u32(),
+            version: reader.u32(),
+            flags: reader.u32(),
+            hash_offset: reader.u32(),
+            ident_offset: reader.u32(),
+            n_special_slots: reader.u32(),
+            n_code_slots: reader.u32(),
+            code_limit: reader.u32(),
+            hash_size: reader.u8(),
+            hash_type: reader.u8(),
+            platform: reader.u8(),
+            page_size: reader.u8(),
+            spare2: reader.u32(),
+            scatter_offset: reader.u32(),
+            team_offset: reader.u32(),
+            spare3: reader.u32(),
+            code_limit_64: reader.u64(),
+            exec_seg_base: reader.u64(),
+            exec_seg_limit: reader.u64(),
+            exec_seg_flags: reader.u64(),
+        }
+    }
+
+    fn write_fields(&self, writer: &mut FieldWriter) {
+        writer.u32(self.magic);
+        writer.u32(self.length);
+        writer.u32(self.version);
+        writer.u32(self.flags);
+        writer.u32(self.hash_offset);
+        writer.u32(self.ident_offset);
+        writer.u32(self.n_special_slots);
+        writer.u32(self.n_code_slots);
+        writer.u32(self.code_limit);
+        writer.u8(self.hash_size);
+        writer.u8(self.hash_type);
+        writer.u8(self.platform);
+        writer.u8(self.page_size);
+        writer.u32(self.spare2);
+        writer.u32(self.scatter_offset);
+        writer.u32(self.team_offset);
+        writer.u32(self.spare3);
+        writer.u64(self.code_limit_64);
+        writer.u64(self.exec_seg_base);
+        writer.u64(self.exec_seg_limit);
+        writer.u64(self.exec_seg_flags);
     }
 }
@@ -1,3 +1,4 @@
+pub mod endian;
 #[allow(clippy::module_inception)]
-pub(crate) mod macho;
-pub(crate) mod prelude;
+pub mod macho;
+pub mod prelude;
@@ -5,10 +5,97 @@ pub const MH_MAGIC: u32 = 0xfeedface;
 pub const FAT_MAGIC: u32 = 0xcafebabe;
 pub const FAT_CIGAM: u32 = 0xbebafeca;
 
+pub const MH_OBJECT: u32 = 0x1;
+pub const MH_EXECUTE: u32 = 0x2;
+pub const MH_DYLIB: u32 = 0x6;
+pub const MH_DYLINKER: u32 = 0x7;
+pub const MH_BUNDLE: u32 = 0x8;
+pub const MH_KEXT_BUNDLE: u32 = 0xb;
+
+pub const SECTION_TYPE: u32 = 0xff;
+pub const S_ZEROFILL: u32 = 0x1;
+pub const S_GB_ZEROFILL: u32 = 0xc;
+pub const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+
 pub const LC_REQ_DYLD: u32 = 0x80000000;
 pub const LC_SEGMENT: u32 = 0x01;
 pub const LC_SYMTAB: u32 = 0x02;
 pub const LC_SEGMENT_64: u32 = 0x19;
 pub const LC_CODE_SIGNATURE: u32 = 0x1d;
 pub const LC_LOAD_DYLIB: u32 = 0x0c;
+pub const LC_ID_DYLIB: u32 = 0x0d;
 pub const LC_LOAD_WEAK_DYLIB: u32 = 0x18 | LC_REQ_DYLD;
+pub const LC_RPATH: u32 = 0x1c | LC_REQ_DYLD;
+pub const LC_REEXPORT_DYLIB: u32 = 0x1f | LC_REQ_DYLD;
+pub const LC_LOAD_UPWARD_DYLIB: u32 = 0x23 | LC_REQ_DYLD;
+pub const LC_LAZY_LOAD_DYLIB: u32 = 0x20;
+pub const LC_BUILD_VERSION: u32 = 0x32;
+pub const LC_THREAD: u32 = 0x04;
+pub const LC_UNIXTHREAD: u32 = 0x05;
+pub const LC_DYSYMTAB: u32 = 0x0b;
+pub const LC_LOAD_DYLINKER: u32 = 0x0e;
+pub const LC_ID_DYLINKER: u32 = 0x0f;
+pub const LC_UUID: u32 = 0x1b;
+pub const LC_SEGMENT_SPLIT_INFO: u32 = 0x1e;
+pub const LC_ENCRYPTION_INFO: u32 = 0x21;
+pub const LC_DYLD_INFO: u32 = 0x22;
+pub const LC_DYLD_INFO_ONLY: u32 = 0x22 | LC_REQ_DYLD;
+pub const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+pub const LC_VERSION_MIN_IPHONEOS: u32 = 0x25;
+pub const LC_FUNCTION_STARTS: u32 = 0x26;
+pub const LC_DYLD_ENVIRONMENT: u32 = 0x27;
+pub const LC_MAIN: u32 = 0x28 | LC_REQ_DYLD;
+pub const LC_DATA_IN_CODE: u32 = 0x29;
+pub const LC_SOURCE_VERSION: u32 = 0x2a;
+pub const LC_DYLIB_CODE_SIGN_DRS: u32 = 0x2b;
+pub const LC_ENCRYPTION_INFO_64: u32 = 0x2c;
+pub const LC_LINKER_OPTION: u32 = 0x2d;
+pub const LC_LINKER_OPTIMIZATION_HINT: u32 = 0x2e;
+pub const LC_VERSION_MIN_TVOS: u32 = 0x2f;
+pub const LC_VERSION_MIN_WATCHOS: u32 = 0x30;
+pub const LC_NOTE: u32 = 0x31;
+pub const LC_DYLD_EXPORTS_TRIE: u32 = 0x33 | LC_REQ_DYLD;
+pub const LC_DYLD_CHAINED_FIXUPS: u32 = 0x34 | LC_REQ_DYLD;
+pub const LC_FILESET_ENTRY: u32 = 0x35 | LC_REQ_DYLD;
+
+pub const CPU_ARCH_ABI64: u32 = 0x01000000;
+pub const CPU_TYPE_X86: u32 = 7;
+pub const CPU_TYPE_X86_64: u32 = CPU_TYPE_X86 | CPU_ARCH_ABI64;
+pub const CPU_TYPE_ARM: u32 = 12;
+pub const CPU_TYPE_ARM64: u32 = CPU_TYPE_ARM | CPU_ARCH_ABI64;
+pub const CPU_TYPE_POWERPC: u32 = 18;
+pub const CPU_TYPE_POWERPC64: u32 = CPU_TYPE_POWERPC | CPU_ARCH_ABI64;
+
+pub const CPU_SUBTYPE_MASK: u32 = 0xff000000;
+pub const CPU_SUBTYPE_PTRAUTH_ABI: u32 = 0x80000000;
+pub const CPU_SUBTYPE_ARM64_PTR_AUTH_MASK: u32 = 0x0f000000;
+pub const CPU_SUBTYPE_X86_ALL: u32 = 3;
+pub const CPU_SUBTYPE_X86_64_H: u32 = 8;
+pub const CPU_SUBTYPE_ARM64E: u32 = 2;
+
+pub const PLATFORM_MACOS: u32 = 1;
+pub const PLATFORM_IOS: u32 = 2;
+pub const PLATFORM_TVOS: u32 = 3;
+pub const PLATFORM_WATCHOS: u32 = 4;
+pub const PLATFORM_BRIDGEOS: u32 = 5;
+pub const PLATFORM_MACCATALYST: u32 = 6;
+pub const PLATFORM_IOSSIMULATOR: u32 = 7;
+pub const PLATFORM_TVOSSIMULATOR: u32 = 8;
+pub const PLATFORM_WATCHOSSIMULATOR: u32 = 9;
+pub const PLATFORM_DRIVERKIT: u32 = 10;
+pub const PLATFORM_VISIONOS: u32 = 11;
+pub const PLATFORM_VISIONOSSIMULATOR: u32 = 12;
+
+pub const TOOL_CLANG: u32 = 1;
+pub const TOOL_SWIFT: u32 = 2;
+pub const TOOL_LD: u32 = 3;
+pub const TOOL_LLD: u32 = 4;
+
+pub const CSMAGIC_REQUIREMENTS: u32 = 0xfade0c01;
+pub const CSMAGIC_CODEDIRECTORY: u32 = 0xfade0c02;
+pub const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade0cc0;
+pub const CSSLOT_CODEDIRECTORY: u32 = 0;
+pub const CSSLOT_REQUIREMENTS: u32 = 2;
+pub const CS_ADHOC: u32 = 0x2;
+pub const CS_HASHTYPE_SHA256: u8 = 2;
+pub const CS_EXECSEG_MAIN_BINARY: u64 = 0x1;
@@ -1,450 +1,609 @@
-use std::cmp::min;
-use std::fs::{File, OpenOptions};
-use std::io;
-use std::io::prelude::*;
-use std::io::SeekFrom;
-
-mod extensions;
-mod macho;
-mod opts;
-mod utils;
-
-use extensions::InsertDylibFileExt;
-use macho::macho::*;
-use macho::prelude::*;
-use opts::Opts;
-use utils::*;
-
-fn main() -> std::io::Result<()> {
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::Path;
+use std::process;
+
+use insert_dylib_rs::arch::*;
+use insert_dylib_rs::cli::parse_arg;
+use insert_dylib_rs::diff::{diff_images, read_image};
+use insert_dylib_rs::error::{InsertDylibError, Result};
+use insert_dylib_rs::extensions::InsertDylibFileExt;
+use insert_dylib_rs::json;
+use insert_dylib_rs::macho::prelude::*;
+use insert_dylib_rs::ops::check::lint_binary;
+use insert_dylib_rs::ops::dump::dump_load_commands;
+use insert_dylib_rs::ops::info::read_slice_info;
+use insert_dylib_rs::ops::list::list_dependencies;
+use insert_dylib_rs::ops::provenance::read_provenance;
+use insert_dylib_rs::ops::read_mach_header;
+use insert_dylib_rs::opts::{Operation, Opts};
+use insert_dylib_rs::output::{backup, lock_path, AtomicOutput};
+use insert_dylib_rs::patch::{patch_binary, Patched};
+use insert_dylib_rs::patchfile::PatchFile;
+use insert_dylib_rs::record::{Recorder, WriteLog};
+use insert_dylib_rs::report::{Format, Reporter};
+use insert_dylib_rs::storage::Storage;
+use insert_dylib_rs::utils::*;
+use serde_json::Value;
+
+/// What a command adds to the JSON document, besides what it reported through `report`
+#[derive(Default)]
+struct Outcome {
+    slices: Vec<Value>,
+    diff: Option<Value>,
+    /// Fails the command after its slices were reported, e.g. when `check` found issues
+    error: Option<InsertDylibError>,
+}
+
+impl Outcome {
+    fn slices(slices: Vec<Value>) -> Outcome {
+        Outcome {
+            slices,
+            ..Outcome::default()
+        }
+    }
+}
+
+fn main() {
     let options = parse_arg();
-    let lc_name = match options.weak {
-        true => "LC_LOAD_WEAK_DYLIB",
-        false => "LC_LOAD_DYLIB",
+    let mut reporter = Reporter::new(options.format);
+    let (done, todo) = describe_operation(&options);
+
+    let result = match options.operation {
+        Operation::List => print_dylibs(&options, &mut reporter).map(Outcome::slices),
+        Operation::Info => print_info(&options).map(Outcome::slices),
+        Operation::Dump => print_dump(&options).map(Outcome::slices),
+        Operation::History => print_history(&options, &mut reporter).map(Outcome::slices),
+        Operation::Diff(ref other_path) => print_diff(&options, other_path),
+        Operation::Check => print_check(&options),
+        _ => run(&options, &done, &mut reporter),
     };
-
-    std::fs::copy(&options.binary_path, &options.output_path)?;
-    let mut binary_file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(&options.output_path)?;
-    let mut filesize = binary_file.metadata()?.len();
-
-    let mut magic_buffer = [0; 4];
-    binary_file.read_exact(&mut magic_buffer)?;
-
-    let magic_buffer = unsafe { std::mem::transmute::<[u8; 4], u32>(magic_buffer) };
-
-    match magic_buffer {
-        MH_CIGAM_64 | MH_MAGIC_64 | MH_CIGAM | MH_MAGIC => {
-            if insert_dylib(&mut binary_file, 0, &options, &mut filesize)? {
-                binary_file.set_len(filesize as u64)?;
-                println!("Added {} to {}", lc_name, options.binary_path);
-            } else {
-                println!("Failed to add {}", lc_name);
+    match options.format {
+        Format::Text => {
+            if let Err(e) | Ok(Outcome { error: Some(e), .. }) = result {
+                println!("Failed to {} {}: {}", todo, options.binary_path, e);
+                process::exit(e.exit_code());
             }
         }
-        FAT_MAGIC | FAT_CIGAM => {
-            let is_little_endian = magic_buffer == FAT_CIGAM;
-            binary_file.seek(SeekFrom::Start(0))?;
-
-            let mut fat_header_buffer = [0u8; 8];
-            binary_file.read_exact(&mut fat_header_buffer)?;
-            let mut fh = FatHeader::from(fat_header_buffer, is_little_endian);
-
-            let nfat_arch = fh.nfat_arch as usize;
-            println!("Binary is a fat binary with {} archs.", nfat_arch);
-
-            let mut archs: Vec<FatArch> = Vec::new();
-            for _arch_index in 0..nfat_arch {
-                let mut arch_buffer = [0u8; 20];
-                binary_file.read_exact(&mut arch_buffer)?;
-                archs.push(FatArch::from(arch_buffer, is_little_endian));
-            }
-
-            let mut fails = 0usize;
-            let mut offset: u64 = 0;
-            if nfat_arch > 0 {
-                offset = archs[0].offset as u64;
-                if !is_little_endian {
-                    offset = offset.swap_bytes();
+        Format::Json => {
+            let (outcome, error) = match result {
+                Ok(mut outcome) => {
+                    let error = outcome.error.take();
+                    (outcome, error)
                 }
+                Err(e) => (Outcome::default(), Some(e)),
+            };
+            let document = json::document(
+                &options,
+                outcome.slices,
+                outcome.diff,
+                reporter.take(),
+                error.as_ref(),
+            );
+            println!("{:#}", document);
+            if let Some(e) = error {
+                process::exit(e.exit_code());
             }
+        }
+    }
+}
 
-            let _ = archs
-                .iter_mut()
-                .enumerate()
-                .map(|(arch_index, current_arch)| -> io::Result<()> {
-                    let orig_offset = current_arch.offset as u64;
-                    let orig_slice_size = current_arch.size as u64;
-
-                    let mut align: u32 = 1 << current_arch.align;
-                    if !is_little_endian {
-                        align = align.swap_bytes();
-                    }
-
-                    offset = round_up_u64(offset, align as u64);
-
-                    if orig_offset != offset {
-                        binary_file.fmemmove(offset, orig_offset, orig_slice_size)?;
-                        let diff = (offset as i64 - orig_offset as i64).unsigned_abs();
-                        binary_file.fbzero(min(offset, orig_offset) + orig_slice_size, diff)?;
-                        current_arch.offset = offset as u32;
-                    }
-
-                    let mut slice_size = orig_slice_size;
-                    let ret = insert_dylib(&mut binary_file, offset, &options, &mut slice_size)?;
-                    if !ret {
-                        println!("Failed to add {} to arch #{}", lc_name, arch_index + 1);
-                        fails += 1;
-                    }
-
-                    if slice_size < orig_slice_size && arch_index < nfat_arch - 1 {
-                        binary_file.fbzero(offset + slice_size, orig_slice_size - slice_size)?;
-                    }
-
-                    filesize = offset + slice_size;
-                    offset += slice_size;
-                    current_arch.size = slice_size as u32;
-
-                    Ok(())
-                })
-                .collect::<Vec<io::Result<()>>>();
-
-            binary_file.seek(SeekFrom::Start(0))?;
-            if is_little_endian {
-                fh.fix_endian();
-            }
-            binary_file.write_all(&fh.to_u8())?;
-
-            let _ = archs
-                .iter_mut()
-                .map(|current_arch| -> io::Result<()> {
-                    if is_little_endian {
-                        current_arch.fix_endian();
-                    }
-
-                    binary_file.write_all(&current_arch.to_u8())?;
-                    Ok(())
-                })
-                .collect::<Vec<io::Result<()>>>();
-
-            binary_file.set_len(filesize as u64)?;
-            if fails == 0 {
-                println!("Added {} to all archs in {}", lc_name, options.binary_path);
-            } else if fails != nfat_arch {
-                println!(
-                    "Added {} to {}/{} archs in {}",
-                    lc_name,
-                    nfat_arch - fails,
-                    nfat_arch,
-                    options.binary_path
-                )
-            } else {
-                println!("Failed to add {} to any archs.", lc_name)
-            }
+/// Patch the binary, the slices are reported through `Reporter::slice`
+fn run(options: &Opts, done: &str, reporter: &mut Reporter) -> Result<Outcome> {
+    let dylib_archs = match options.operation {
+        Operation::Insert => read_dylib_archs(options, reporter)?,
+        _ => Vec::new(),
+    };
+    if options.dry_run {
+        return dry_run(options, &dylib_archs, reporter);
+    }
+
+    // hold the lock until the patched binary has replaced the original
+    let _lock = match options.inplace {
+        true => Some(lock_path(Path::new(&options.binary_path))?),
+        false => None,
+    };
+
+    // patch a temporary copy, the output path only ever sees a fully patched binary
+    let mut output = AtomicOutput::copy_from(
+        Path::new(&options.binary_path),
+        Path::new(&options.output_path),
+    )?;
+    let result = match (&options.operation, &options.write_log) {
+        (Operation::ApplyPatch(patch_path), _) => apply_patch_file(output.file(), patch_path, true),
+        (Operation::RevertPatch(patch_path), _) => {
+            apply_patch_file(output.file(), patch_path, false)
         }
-        _ => {
-            println!("Not a MachO binary: {}", options.binary_path);
+        (Operation::UndoWriteLog(write_log), _) => undo_write_log(output.file(), write_log),
+        (_, Some(write_log)) => {
+            record_patch(output.file(), options, &dylib_archs, write_log, reporter)
         }
+        (_, None) => patch_binary(output.file(), options, &dylib_archs, reporter),
+    };
+    let result = result.and_then(|patched| match options.verify_output {
+        true => {
+            verify_output(options, output.file(), reporter)?;
+            Ok(patched)
+        }
+        false => Ok(patched),
+    });
+    let result = result.and_then(|patched| match &options.export_patch {
+        Some(export_patch) => {
+            export_patch_file(options, output.file(), export_patch, reporter)?;
+            Ok(patched)
+        }
+        None => Ok(patched),
+    });
+    let patched = match result {
+        Ok(patched) => patched,
+        Err(e) => {
+            reporter.note(format!(
+                "Left {} untouched, the binary was not patched.",
+                options.output_path
+            ));
+            return Err(e);
+        }
+    };
+    if let Some(suffix) = &options.backup_suffix {
+        let backup_path = backup(Path::new(&options.binary_path), suffix)?;
+        reporter.note(format!(
+            "Backed up {} to {}",
+            options.binary_path,
+            backup_path.display()
+        ));
     }
+    output.commit(options.preserve_mtime, reporter)?;
 
-    Ok(())
+    match patched.fat {
+        true => reporter.note(format!("{} all archs in {}", done, options.binary_path)),
+        false => reporter.note(format!("{} {}", done, options.binary_path)),
+    }
+
+    Ok(Outcome::default())
+}
+
+/// Apply the patch file to the original binary in `binary_file`, or revert it from the patched
+/// one
+fn apply_patch_file(binary_file: &mut File, patch_path: &str, apply: bool) -> Result<Patched> {
+    let patch = PatchFile::parse(&fs::read(patch_path)?)?;
+    match apply {
+        true => patch.apply(binary_file)?,
+        false => patch.revert(binary_file)?,
+    }
+    Ok(Patched {
+        filesize: binary_file.flen()?,
+        fat: false,
+    })
+}
+
+/// Undo the writes recorded in the write log at `write_log_path` on the output in `binary_file`
+fn undo_write_log(binary_file: &mut File, write_log_path: &str) -> Result<Patched> {
+    let value: Value = serde_json::from_slice(&fs::read(write_log_path)?)
+        .map_err(|e| InsertDylibError::BadPatch(format!("the write log is not JSON ({})", e)))?;
+    WriteLog::from_json(&value)?.revert(binary_file)?;
+    Ok(Patched {
+        filesize: binary_file.flen()?,
+        fat: false,
+    })
 }
 
-fn insert_dylib(
+/// Save a patch file from the input binary to the patched one in `binary_file`
+fn export_patch_file(
+    options: &Opts,
     binary_file: &mut File,
-    header_offset: u64,
+    export_patch: &str,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let mut original = File::open(&options.binary_path)?;
+    let patch = PatchFile::between(&mut original, binary_file)?;
+    let bytes = patch.to_bytes();
+    fs::write(export_patch, &bytes)?;
+    reporter.note(format!(
+        "Saved a patch with {} ranges ({} bytes) to {}",
+        patch.ranges.len(),
+        bytes.len(),
+        export_patch
+    ));
+    Ok(())
+}
+
+/// Check the patched binary in `binary_file` against the structural rules dyld enforces
+///
+/// Issues the input binary already had are only warned about, new ones fail the command. Issues
+/// are told apart by rule and subject, as their messages hold offsets the patch may have moved.
+fn verify_output<F: InsertDylibFileExt>(
     options: &Opts,
-    slice_size: &mut u64,
-) -> io::Result<bool> {
-    binary_file.seek(SeekFrom::Start(header_offset))?;
-
-    let mut header_buffer = [0u8; 32];
-    binary_file.read_exact(&mut header_buffer)?;
-
-    let mut mach_header = MachHeader::from(header_buffer);
-    match mach_header.magic {
-        MH_CIGAM_64 | MH_MAGIC_64 | MH_CIGAM | MH_MAGIC => (),
-        _ => {
-            println!("Unknown MachO header magic: {:08x}", mach_header.magic);
+    binary_file: &mut F,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (before, _) = lint_binary(&mut File::open(&options.binary_path)?)?;
+    let (after, _) = lint_binary(binary_file)?;
+
+    let mut new_issues = 0;
+    // operations never add or reorder slices, so they pair up by index
+    for (index, slice) in after.iter().enumerate() {
+        let input_issues = before.get(index).map_or(&[][..], |input| &input.issues[..]);
+        for issue in &slice.issues {
+            let known = input_issues.iter().any(|other| other.same_as(issue));
+            match known {
+                true => {
+                    reporter.warning(format!("{}: {}, already in the input.", slice.arch, issue))
+                }
+                false => {
+                    reporter.warning(format!("{}: {}", slice.arch, issue));
+                    new_issues += 1;
+                }
+            }
         }
     }
-
-    let commands_offset = header_offset + mach_header.len();
-    let cont = check_load_commands(
-        binary_file,
-        &mut mach_header,
-        header_offset,
-        commands_offset,
-        options,
-        slice_size,
-    )?;
-    if !cont {
-        return Ok(true);
+    match new_issues {
+        0 => Ok(()),
+        _ => Err(InsertDylibError::CheckFailed(new_issues)),
     }
+}
+
+/// Patch the binary through a `Recorder` and save what it wrote to `write_log`
+fn record_patch(
+    binary_file: &mut File,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    write_log: &str,
+    reporter: &mut Reporter,
+) -> Result<Patched> {
+    let mut recorder = Recorder::new(binary_file)?;
+    let patched = patch_binary(&mut recorder, options, dylib_archs, reporter)?;
+    let log = recorder.log();
+    fs::write(write_log, format!("{:#}\n", log.to_json()))?;
+    reporter.note(format!(
+        "Saved {} writes to {}",
+        log.changes.len(),
+        write_log
+    ));
+    Ok(patched)
+}
 
-    let path_padding = 8u32;
-    let dylib_path_len = options.dylib_path.len() as u32;
-    let dylib_path_size = (dylib_path_len & !(path_padding - 1)) + path_padding;
-    let cmdsize: u32 = dylib_path_size + DylibCommand::len() as u32;
+/// Patch an in-memory copy of the binary and show what would change
+fn dry_run(options: &Opts, dylib_archs: &[SliceArch], reporter: &mut Reporter) -> Result<Outcome> {
+    let mut binary = Cursor::new(fs::read(&options.binary_path)?);
+    let before = read_image(&mut binary)?;
+    patch_binary(&mut binary, options, dylib_archs, reporter)?;
+    if options.verify_output {
+        verify_output(options, &mut binary, reporter)?;
+    }
+    let after = read_image(&mut binary)?;
 
-    let mut dylib_command = DylibCommand::default();
-    dylib_command.cmd = match options.weak {
-        true => LC_LOAD_WEAK_DYLIB,
-        false => LC_LOAD_DYLIB,
+    let diff = diff_images(&before, &after);
+    let outcome = match options.format {
+        Format::Text => {
+            print!("{}", diff);
+            Outcome::default()
+        }
+        Format::Json => Outcome {
+            diff: Some(json::binary_diff(&diff)),
+            ..Outcome::default()
+        },
     };
-    dylib_command.cmdsize = cmdsize;
-    dylib_command.dylib.name_offset = DylibCommand::len() as u32;
-    dylib_command.dylib.timestamp = 0;
-    dylib_command.dylib.current_version = 0;
-    dylib_command.dylib.compatibility_version = 0;
-
-    let mut sizeofcmds = mach_header.sizeofcmds;
-    if MachHeader::is_little_endian(mach_header.magic) {
-        sizeofcmds = sizeofcmds.swap_bytes();
-        dylib_command.fix_endian();
+    reporter.note(format!("Dry run, {} was not written.", options.output_path));
+
+    Ok(outcome)
+}
+
+/// Past and infinitive phrases describing the requested operation, e.g. `("Added LC_LOAD_DYLIB to", "add LC_LOAD_DYLIB to")`
+fn describe_operation(options: &Opts) -> (String, String) {
+    match options.operation {
+        Operation::Insert => {
+            let lc_name = match options.weak {
+                true => "LC_LOAD_WEAK_DYLIB",
+                false => "LC_LOAD_DYLIB",
+            };
+            (
+                format!("Added {} to", lc_name),
+                format!("add {} to", lc_name),
+            )
+        }
+        Operation::SetWeak(weak) => {
+            let lc_name = match weak {
+                true => "LC_LOAD_WEAK_DYLIB",
+                false => "LC_LOAD_DYLIB",
+            };
+            (
+                format!("Changed {} to {} in", options.dylib_path, lc_name),
+                format!("change {} to {} in", options.dylib_path, lc_name),
+            )
+        }
+        Operation::SetId(ref install_name) => (
+            format!("Changed install name to {} in", install_name),
+            format!("change install name to {} in", install_name),
+        ),
+        Operation::Rewrite(_) => (
+            "Rewrote paths in".to_string(),
+            "rewrite paths in".to_string(),
+        ),
+        Operation::Dedupe { .. } => (
+            "Removed duplicate dylibs from".to_string(),
+            "remove duplicate dylibs from".to_string(),
+        ),
+        Operation::Remove => (
+            format!("Removed {} from", options.dylib_path),
+            format!("remove {} from", options.dylib_path),
+        ),
+        Operation::StripSignature => (
+            "Stripped the code signature from".to_string(),
+            "strip the code signature from".to_string(),
+        ),
+        Operation::Sign { .. } => ("Ad-hoc signed".to_string(), "ad-hoc sign".to_string()),
+        Operation::List => ("Listed dylibs of".to_string(), "list dylibs of".to_string()),
+        Operation::Info => ("Read".to_string(), "read".to_string()),
+        Operation::Dump => ("Dumped".to_string(), "dump".to_string()),
+        Operation::History => (
+            "Read the history of".to_string(),
+            "read the history of".to_string(),
+        ),
+        Operation::Diff(ref other_path) => (
+            format!("Compared {} with", other_path),
+            format!("compare {} with", other_path),
+        ),
+        Operation::Check => ("Checked".to_string(), "check".to_string()),
+        Operation::ApplyPatch(ref patch_path) => (
+            format!("Applied {} to", patch_path),
+            format!("apply {} to", patch_path),
+        ),
+        Operation::RevertPatch(ref patch_path) => (
+            format!("Reverted {} in", patch_path),
+            format!("revert {} in", patch_path),
+        ),
+        Operation::UndoWriteLog(ref write_log) => (
+            format!("Undid the writes in {} to", write_log),
+            format!("undo the writes in {} to", write_log),
+        ),
     }
-    binary_file.seek(SeekFrom::Start(commands_offset + sizeofcmds as u64))?;
+}
 
-    let mut space: Vec<u8> = vec![0; cmdsize as usize];
-    binary_file.read_exact(&mut space[..])?;
+/// Print the dylibs and rpaths of every slice, like `otool -L`, or return them for JSON
+fn print_dylibs(options: &Opts, reporter: &mut Reporter) -> Result<Vec<Value>> {
+    let mut binary_file = File::open(&options.binary_path)?;
+    let (slices, fat) = read_slice_ranges(&mut binary_file)?;
+
+    let mut values = Vec::new();
+    for slice in slices {
+        let arch = read_slice_arch(&mut binary_file, slice.offset)?;
+        let dependencies = list_dependencies(&mut binary_file, slice.offset, reporter)?;
+        if options.format == Format::Json {
+            values.push(json::dependencies(&arch, &dependencies));
+            continue;
+        }
 
-    let mut empty = true;
-    for item in space.iter().take(cmdsize as usize) {
-        if *item != 0 {
-            empty = false;
-            break;
+        match fat {
+            true => println!("{} (architecture {}):", options.binary_path, arch.name()),
+            false => println!("{}:", options.binary_path),
+        }
+        for dylib in &dependencies.dylibs {
+            let kind = match dylib.cmd {
+                LC_ID_DYLIB => ", id",
+                LC_LOAD_WEAK_DYLIB => ", weak",
+                LC_REEXPORT_DYLIB => ", reexport",
+                LC_LOAD_UPWARD_DYLIB => ", upward",
+                LC_LAZY_LOAD_DYLIB => ", lazy",
+                _ => "",
+            };
+            println!(
+                "\t{} (compatibility version {}, current version {}{})",
+                dylib.path.as_deref().unwrap_or("<unreadable path>"),
+                format_version(dylib.compatibility_version),
+                format_version(dylib.current_version),
+                kind
+            );
+        }
+        for rpath in &dependencies.rpaths {
+            println!("\t{} (rpath)", rpath);
         }
     }
 
-    if !empty {
-        println!("It doesn't seem like there is enough empty space. Will continue though...");
+    Ok(values)
+}
+
+/// Print a summary of every slice, or return it for JSON
+fn print_info(options: &Opts) -> Result<Vec<Value>> {
+    let mut binary_file = File::open(&options.binary_path)?;
+    let (slices, fat) = read_slice_ranges(&mut binary_file)?;
+
+    if options.format == Format::Json {
+        return slices
+            .into_iter()
+            .map(|slice| Ok(json::slice_info(&read_slice_info(&mut binary_file, slice)?)))
+            .collect();
     }
 
-    binary_file.seek(SeekFrom::Current(0 - cmdsize as i64))?;
-    binary_file.write_all(&dylib_command.to_u8())?;
-    binary_file.write_all(options.dylib_path.as_ref())?;
+    match fat {
+        true => println!(
+            "{}: fat binary with {} archs",
+            options.binary_path,
+            slices.len()
+        ),
+        false => println!("{}: thin binary", options.binary_path),
+    }
+    for slice in slices {
+        let info = read_slice_info(&mut binary_file, slice)?;
+        println!("{}:", info.arch.name());
+        println!(
+            "    offset {:#x}, size {:#x}",
+            info.range.offset, info.range.size
+        );
+        println!("    filetype: {}", filetype_name(info.filetype));
+        if let Some((platform, minos)) = info.arch.build_version {
+            println!(
+                "    platform: {} {}",
+                platform_name(platform),
+                format_version(minos)
+            );
+        }
+        println!(
+            "    load commands: {} ({} bytes), {} bytes free after them",
+            info.ncmds, info.sizeofcmds, info.free_space
+        );
+        if let Some(install_name) = &info.install_name {
+            println!("    install name: {}", install_name);
+        }
+        println!(
+            "    code signature: {}",
+            match info.signed {
+                true => "yes",
+                false => "no",
+            }
+        );
+    }
+
+    Ok(Vec::new())
+}
+
+/// Print the header and every load command of every slice, like `otool -l`, or return them for
+/// JSON
+fn print_dump(options: &Opts) -> Result<Vec<Value>> {
+    let mut binary_file = File::open(&options.binary_path)?;
+    let (slices, fat) = read_slice_ranges(&mut binary_file)?;
+
+    let mut values = Vec::new();
+    for slice in slices {
+        let arch = read_slice_arch(&mut binary_file, slice.offset)?;
+        let (mach_header, _) = read_mach_header(&mut binary_file, slice.offset)?;
+        let commands = dump_load_commands(&mut binary_file, slice.offset)?;
+        if options.format == Format::Json {
+            values.push(json::load_commands(&arch, &mach_header, &commands));
+            continue;
+        }
 
-    sizeofcmds += cmdsize;
+        match fat {
+            true => println!("{} (architecture {}):", options.binary_path, arch.name()),
+            false => println!("{}:", options.binary_path),
+        }
 
-    if MachHeader::is_little_endian(mach_header.magic) {
-        mach_header.ncmds = (mach_header.ncmds.swap_bytes() + 1).swap_bytes();
-        mach_header.sizeofcmds = sizeofcmds.swap_bytes();
-    } else {
-        mach_header.ncmds += 1;
-        mach_header.sizeofcmds = sizeofcmds;
+        println!("Mach header");
+        println!("      magic  cputype cpusubtype  caps filetype ncmds sizeofcmds      flags");
+        println!(
+            " {:#010x} {:>8} {:>10} {:#04x} {:>8} {:>5} {:>10} {:#010x}",
+            mach_header.magic,
+            mach_header.cputype,
+            mach_header.cpusubtype & !CPU_SUBTYPE_MASK,
+            mach_header.cpusubtype >> 24,
+            mach_header.filetype,
+            mach_header.ncmds,
+            mach_header.sizeofcmds,
+            mach_header.flags
+        );
+        for command in commands {
+            print!("{}", command);
+        }
     }
 
-    binary_file.seek(SeekFrom::Start(header_offset))?;
-    binary_file.write_all(&mach_header.to_u8())?;
+    Ok(values)
+}
+
+/// Print how the binary at `other_path` differs from the binary, or return it for JSON
+fn print_diff(options: &Opts, other_path: &str) -> Result<Outcome> {
+    let before = read_image(&mut File::open(&options.binary_path)?)?;
+    let after = read_image(&mut File::open(other_path)?)?;
+    let diff = diff_images(&before, &after);
+
+    if options.format == Format::Json {
+        return Ok(Outcome {
+            diff: Some(json::binary_diff(&diff)),
+            ..Outcome::default()
+        });
+    }
+    println!("--- {}", options.binary_path);
+    println!("+++ {}", other_path);
+    print!("{}", diff);
 
-    Ok(true)
+    Ok(Outcome::default())
 }
 
-fn check_load_commands(
-    binary_file: &mut File,
-    mach_header: &mut MachHeader,
-    header_offset: u64,
-    commands_offset: u64,
-    options: &Opts,
-    slice_size: &mut u64,
-) -> io::Result<bool> {
-    binary_file.seek(SeekFrom::Start(commands_offset))?;
-
-    let is_little_endian = MachHeader::is_little_endian(mach_header.magic);
-
-    let ncmds = mach_header.ncmds;
-
-    let mut linkedit_32_pos = -1i64;
-    let mut linkedit_64_pos = -1i64;
-    let mut linkedit_32 = SegmentCommand::default();
-    let mut linkedit_64 = SegmentCommand64::default();
-
-    let mut symtab_pos = -1i64;
-
-    let mut skip_fbzero_before_fix_header = false;
-    for i in 0..ncmds {
-        let mut load_command_buffer = [0u8; 8];
-        binary_file.fpeek(&mut load_command_buffer)?;
-
-        let lc = LoadCommand::from(load_command_buffer, is_little_endian);
-
-        match lc.cmd {
-            LC_CODE_SIGNATURE => {
-                if i == ncmds - 1 {
-                    if !options.strip_codesign {
-                        return Ok(true);
-                    }
-
-                    let mut linkedit_data_command_buffer = [0u8; 16];
-                    binary_file.fpeek(&mut linkedit_data_command_buffer)?;
-
-                    let cmd =
-                        LinkeditDataCommand::from(linkedit_data_command_buffer, is_little_endian);
-
-                    let current_offset = binary_file.ftello();
-                    binary_file.fbzero(current_offset, lc.cmdsize as u64)?;
-
-                    let mut linkedit_fileoff = 0u64;
-                    let mut linkedit_filesize = 0u64;
-
-                    if linkedit_32_pos != -1 {
-                        linkedit_fileoff = linkedit_32.fileoff as u64;
-                        linkedit_filesize = linkedit_32.filesize as u64;
-                    } else if linkedit_64_pos != -1 {
-                        linkedit_fileoff = linkedit_64.fileoff;
-                        linkedit_filesize = linkedit_64.filesize;
-                    } else {
-                        println!("Warning: __LINKEDIT segment not found.");
-                    }
-
-                    if linkedit_32_pos != -1 || linkedit_64_pos != -1 {
-                        if is_little_endian {
-                            linkedit_fileoff = linkedit_fileoff.swap_bytes();
-                            linkedit_filesize = linkedit_filesize.swap_bytes();
-                        }
-
-                        if linkedit_fileoff + linkedit_filesize != *slice_size {
-                            println!("Warning: __LINKEDIT segment is not at the end of the file, so codesign will not work on the patched binary.");
-                        } else if (cmd.dataoff + cmd.datasize) as u64 != *slice_size {
-                            println!("Warning: Codesignature is not at the end of __LINKEDIT segment, so codesign will not work on the patched binary.");
-                        } else {
-                            *slice_size -= cmd.datasize as u64;
-
-                            if symtab_pos == -1 {
-                                println!("Warning: LC_SYMTAB load command not found. codesign might not work on the patched binary.");
-                            } else {
-                                binary_file.seek(SeekFrom::Start(symtab_pos as u64))?;
-                                let mut symtab_command_buffer = [0u8; 24];
-                                binary_file.fpeek(&mut symtab_command_buffer)?;
-                                let mut symtab =
-                                    SymtabCommand::from(symtab_command_buffer, is_little_endian);
-                                let diffsize =
-                                    (symtab.stroff + symtab.strsize) as i64 - (*slice_size as i64);
-                                if (-16..=0).contains(&diffsize) {
-                                    symtab.strsize =
-                                        ((symtab.strsize as i32) - (diffsize as i32)) as u32;
-                                    if is_little_endian {
-                                        symtab.strsize = symtab.strsize.swap_bytes();
-                                    }
-
-                                    binary_file.write_all(&symtab.to_u8())?;
-                                } else {
-                                    println!("Warning: String table doesn't appear right before code signature. codesign might not work on the patched binary. {:016x}", diffsize);
-                                }
-                            }
-
-                            linkedit_filesize -= cmd.datasize as u64;
-                            let linkedit_vmsize = round_up_u64(linkedit_filesize, 0x1000);
-
-                            if linkedit_32_pos != -1 {
-                                linkedit_32.filesize = linkedit_filesize as u32;
-                                linkedit_32.vmsize = linkedit_vmsize as u32;
-
-                                if is_little_endian {
-                                    linkedit_32.filesize = linkedit_32.filesize.swap_bytes();
-                                    linkedit_32.vmsize = linkedit_32.vmsize.swap_bytes();
-                                }
-
-                                binary_file.seek(SeekFrom::Start(linkedit_32_pos as u64))?;
-                                binary_file.write_all(&linkedit_32.to_u8())?;
-                            } else {
-                                linkedit_64.filesize = linkedit_filesize;
-                                linkedit_64.vmsize = linkedit_vmsize;
-
-                                if is_little_endian {
-                                    linkedit_64.filesize = linkedit_64.filesize.swap_bytes();
-                                    linkedit_64.vmsize = linkedit_64.vmsize.swap_bytes();
-                                }
-
-                                binary_file.seek(SeekFrom::Start(linkedit_64_pos as u64))?;
-                                binary_file.write_all(&linkedit_64.to_u8())?;
-                            }
-
-                            skip_fbzero_before_fix_header = true;
-                        }
-                    }
-
-                    if !skip_fbzero_before_fix_header {
-                        binary_file
-                            .fbzero(header_offset + cmd.dataoff as u64, cmd.datasize as u64)?;
-                    }
-
-                    let new_sizeofcmds = mach_header.sizeofcmds - lc.cmdsize;
-                    fix_header(mach_header, ncmds - 1, new_sizeofcmds);
-                } else {
-                    println!("LC_CODE_SIGNATURE is not the last load command, so couldn't remove.");
-                }
-            }
-            LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB => {
-                let mut dylib_command_buffer = [0u8; 24];
-                binary_file.fpeek(&mut dylib_command_buffer)?;
-                let dylib_command = DylibCommand::from(dylib_command_buffer, is_little_endian);
-
-                let mut dylib_name_buffer: Vec<u8> = vec![0; lc.cmdsize as usize];
-                binary_file.fpeek(&mut dylib_name_buffer)?;
-
-                let dylib_name_start: usize = dylib_command.dylib.name_offset as usize;
-                let dylib_name_max_index = lc.cmdsize as usize;
-                let mut dylib_name_end = 0;
-
-                for (index, buf) in dylib_name_buffer
-                    .iter()
-                    .enumerate()
-                    .take(dylib_name_max_index)
-                    .skip(dylib_name_start)
-                {
-                    if *buf == 0 {
-                        dylib_name_end = index;
-                        break;
-                    }
-                }
-                let name = match String::from_utf8(
-                    dylib_name_buffer[dylib_name_start..dylib_name_end].to_vec(),
-                ) {
-                    Ok(name) => name,
-                    Err(e) => {
-                        println!("Cannot get dylib path for load command at {}: {}", i, e);
-                        continue;
-                    }
-                };
-
-                if name.eq(&options.dylib_path) {
-                    println!("Binary already contains a load command for that dylib.");
-                    return Ok(true);
-                }
-            }
-            LC_SEGMENT | LC_SEGMENT_64 => {
-                if lc.cmd == LC_SEGMENT {
-                    let mut segment_command_buffer = [0u8; 56];
-                    binary_file.fpeek(&mut segment_command_buffer)?;
-                    let cmd = SegmentCommand::from(segment_command_buffer, is_little_endian);
-                    if get_segname(&cmd.segname).eq("__LINKEDIT") {
-                        linkedit_32_pos = binary_file.ftello() as i64;
-                        linkedit_32 = cmd;
-                    }
-                } else {
-                    let mut segment_command_buffer = [0u8; 72];
-                    binary_file.fpeek(&mut segment_command_buffer)?;
-                    let cmd = SegmentCommand64::from(segment_command_buffer, is_little_endian);
-                    if get_segname(&cmd.segname).eq("__LINKEDIT") {
-                        linkedit_64_pos = binary_file.ftello() as i64;
-                        linkedit_64 = cmd;
-                    }
-                }
-            }
-            LC_SYMTAB => {
-                symtab_pos = binary_file.ftello() as i64;
+/// Print the structural issues of every slice, or return them for JSON
+///
+/// Any issue fails the command once every slice was reported.
+fn print_check(options: &Opts) -> Result<Outcome> {
+    let mut binary_file = File::open(&options.binary_path)?;
+    let (slices, fat) = lint_binary(&mut binary_file)?;
+
+    let mut values = Vec::new();
+    for slice in &slices {
+        if options.format == Format::Json {
+            values.push(json::lint(slice));
+            continue;
+        }
+
+        match fat {
+            true => println!("{} (architecture {}):", options.binary_path, slice.arch),
+            false => println!("{}:", options.binary_path),
+        }
+        if slice.issues.is_empty() {
+            println!("\tno issues");
+        }
+        for issue in &slice.issues {
+            println!("\t{}", issue);
+        }
+    }
+
+    let issues: usize = slices.iter().map(|slice| slice.issues.len()).sum();
+    Ok(Outcome {
+        slices: values,
+        error: (issues > 0).then_some(InsertDylibError::CheckFailed(issues)),
+        ..Outcome::default()
+    })
+}
+
+/// Print the provenance records of every slice, oldest first, or return them for JSON
+fn print_history(options: &Opts, reporter: &mut Reporter) -> Result<Vec<Value>> {
+    let mut binary_file = File::open(&options.binary_path)?;
+    let (slices, fat) = read_slice_ranges(&mut binary_file)?;
+
+    let mut values = Vec::new();
+    for slice in slices {
+        let arch = read_slice_arch(&mut binary_file, slice.offset)?;
+        let records = read_provenance(&mut binary_file, slice.offset, reporter)?;
+        if options.format == Format::Json {
+            values.push(json::provenance(&arch, &records));
+            continue;
+        }
+
+        match fat {
+            true => println!("{} (architecture {}):", options.binary_path, arch.name()),
+            false => println!("{}:", options.binary_path),
+        }
+        if records.is_empty() {
+            println!("\tno provenance records");
+        }
+        for (index, record) in records.iter().enumerate() {
+            println!(
+                "\t#{} {} {}: {}",
+                index + 1,
+                record.tool,
+                record.version,
+                record.operation
+            );
+            if !record.arguments.is_empty() {
+                println!("\t    arguments: {}", record.arguments.join(" "));
             }
-            _ => (),
+            println!("\t    original sha256: {}", record.original_sha256);
         }
-        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
     }
 
-    Ok(true)
+    Ok(values)
 }
 
-fn fix_header(mach_header: &mut MachHeader, ncmds: u32, sizeofcmds: u32) {
-    mach_header.ncmds = ncmds;
-    mach_header.sizeofcmds = sizeofcmds;
+fn read_dylib_archs(options: &Opts, reporter: &mut Reporter) -> Result<Vec<SliceArch>> {
+    let dylib_file = match &options.dylib_file {
+        Some(dylib_file) => dylib_file,
+        None if Path::new(&options.dylib_path).is_file() => &options.dylib_path,
+        None => return Ok(Vec::new()),
+    };
+
+    let archs = read_archs(&mut File::open(dylib_file)?)?;
+    if archs.is_empty() {
+        reporter.warning(format!(
+            "{} is not a MachO file, skipping architecture check.",
+            dylib_file
+        ));
+    }
+
+    Ok(archs)
 }
@@ -0,0 +1,184 @@
+use std::io::SeekFrom;
+
+use super::read_mach_header;
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+
+/// Largest slice alignment in a fat binary, as a power of 2
+const MAX_FAT_ALIGN: u32 = 15;
+
+/// Read the fat header and its archs, checking that every slice lies inside the file
+///
+/// Slices must be sorted by offset and must not overlap each other or the fat header, since they
+/// are moved in place when an earlier slice shrinks.
+pub fn read_fat_archs<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    fat_endian: Endian,
+) -> Result<(FatHeader, Vec<FatArch>)> {
+    let file_len = binary_file.flen()?;
+    binary_file.seek(SeekFrom::Start(0))?;
+
+    let mut fat_header_buffer = [0u8; FatHeader::SIZE];
+    binary_file.read_exact(&mut fat_header_buffer)?;
+    let fh = FatHeader::from_bytes(&fat_header_buffer, fat_endian);
+
+    let archs_end = FatHeader::SIZE as u64 + fh.nfat_arch as u64 * FatArch::SIZE as u64;
+    if archs_end > file_len {
+        return Err(InsertDylibError::TooManyArchs(fh.nfat_arch));
+    }
+
+    let mut archs: Vec<FatArch> = Vec::new();
+    let mut previous_end = archs_end;
+    for index in 0..fh.nfat_arch as usize {
+        let mut arch_buffer = [0u8; FatArch::SIZE];
+        binary_file.read_exact(&mut arch_buffer)?;
+        let arch = FatArch::from_bytes(&arch_buffer, fat_endian);
+
+        let start = arch.offset as u64;
+        let end = start + arch.size as u64;
+        if start < previous_end || end > file_len || arch.align > MAX_FAT_ALIGN {
+            return Err(InsertDylibError::BadFatArch {
+                index,
+                offset: arch.offset,
+                size: arch.size,
+                align: arch.align,
+            });
+        }
+
+        previous_end = end;
+        archs.push(arch);
+    }
+
+    Ok((fh, archs))
+}
+
+/// Check that the load commands of the slice at `header_offset`, and the data they refer to, lie
+/// inside the slice
+///
+/// The operations walk the load commands trusting `cmdsize` and the offsets stored in them, so a
+/// slice has to pass this check before anything else touches it.
+pub fn check_slice<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    slice_size: u64,
+) -> Result<()> {
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    if mach_header.len() + mach_header.sizeofcmds as u64 > slice_size {
+        return Err(InsertDylibError::LoadCommandsOutOfBounds {
+            sizeofcmds: mach_header.sizeofcmds,
+        });
+    }
+
+    let mut commands_left = mach_header.sizeofcmds;
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for index in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+        if (lc.cmdsize as usize) < min_command_size(lc.cmd) || lc.cmdsize > commands_left {
+            return Err(InsertDylibError::BadCommandSize {
+                index,
+                cmd: lc.cmd,
+                cmdsize: lc.cmdsize,
+            });
+        }
+
+        let check_str = |str_offset: u32, fixed_len: usize| {
+            if (str_offset as usize) < fixed_len || str_offset >= lc.cmdsize {
+                return Err(InsertDylibError::BadStringOffset {
+                    index,
+                    offset: str_offset,
+                    cmdsize: lc.cmdsize,
+                });
+            }
+            Ok(())
+        };
+        let check_data = |offset: u64, size: u64| match offset.checked_add(size) {
+            Some(end) if end <= slice_size => Ok(()),
+            _ => Err(InsertDylibError::DataOutOfBounds {
+                index,
+                cmd: lc.cmd,
+                offset,
+                size,
+            }),
+        };
+
+        match lc.cmd {
+            LC_ID_DYLIB | LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB
+            | LC_LOAD_UPWARD_DYLIB => {
+                let cmd: DylibCommand = binary_file.fpeek_struct(endian)?;
+                check_str(cmd.dylib.name_offset, DylibCommand::SIZE)?;
+            }
+            LC_RPATH => {
+                let cmd: RpathCommand = binary_file.fpeek_struct(endian)?;
+                check_str(cmd.path_offset, RpathCommand::SIZE)?;
+            }
+            LC_LOAD_DYLINKER | LC_ID_DYLINKER | LC_DYLD_ENVIRONMENT => {
+                let cmd: DylinkerCommand = binary_file.fpeek_struct(endian)?;
+                check_str(cmd.name_offset, DylinkerCommand::SIZE)?;
+            }
+            LC_SEGMENT => {
+                let cmd: SegmentCommand = binary_file.fpeek_struct(endian)?;
+                check_data(cmd.fileoff as u64, cmd.filesize as u64)?;
+            }
+            LC_SEGMENT_64 => {
+                let cmd: SegmentCommand64 = binary_file.fpeek_struct(endian)?;
+                check_data(cmd.fileoff, cmd.filesize)?;
+            }
+            LC_SYMTAB => {
+                let cmd: SymtabCommand = binary_file.fpeek_struct(endian)?;
+                check_data(cmd.stroff as u64, cmd.strsize as u64)?;
+            }
+            LC_CODE_SIGNATURE => {
+                let cmd: LinkeditDataCommand = binary_file.fpeek_struct(endian)?;
+                check_data(cmd.dataoff as u64, cmd.datasize as u64)?;
+            }
+            LC_NOTE => {
+                let cmd: NoteCommand = binary_file.fpeek_struct(endian)?;
+                check_data(cmd.offset, cmd.size)?;
+            }
+            _ => (),
+        }
+
+        commands_left -= lc.cmdsize;
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    Ok(())
+}
+
+/// Smallest valid `cmdsize` of a load command of type `cmd`
+fn min_command_size(cmd: u32) -> usize {
+    match cmd {
+        LC_ID_DYLIB | LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB
+        | LC_LOAD_UPWARD_DYLIB => DylibCommand::SIZE,
+        LC_RPATH => RpathCommand::SIZE,
+        LC_SEGMENT => SegmentCommand::SIZE,
+        LC_SEGMENT_64 => SegmentCommand64::SIZE,
+        LC_SYMTAB => SymtabCommand::SIZE,
+        LC_CODE_SIGNATURE
+        | LC_SEGMENT_SPLIT_INFO
+        | LC_FUNCTION_STARTS
+        | LC_DATA_IN_CODE
+        | LC_DYLIB_CODE_SIGN_DRS
+        | LC_LINKER_OPTIMIZATION_HINT
+        | LC_DYLD_EXPORTS_TRIE
+        | LC_DYLD_CHAINED_FIXUPS => LinkeditDataCommand::SIZE,
+        LC_BUILD_VERSION => BuildVersionCommand::SIZE,
+        LC_DYSYMTAB => DysymtabCommand::SIZE,
+        LC_LOAD_DYLINKER | LC_ID_DYLINKER | LC_DYLD_ENVIRONMENT => DylinkerCommand::SIZE,
+        LC_UUID => UuidCommand::SIZE,
+        LC_ENCRYPTION_INFO | LC_ENCRYPTION_INFO_64 => EncryptionInfoCommand::SIZE,
+        LC_DYLD_INFO | LC_DYLD_INFO_ONLY => DyldInfoCommand::SIZE,
+        LC_VERSION_MIN_MACOSX
+        | LC_VERSION_MIN_IPHONEOS
+        | LC_VERSION_MIN_TVOS
+        | LC_VERSION_MIN_WATCHOS => VersionMinCommand::SIZE,
+        LC_MAIN => EntryPointCommand::SIZE,
+        LC_SOURCE_VERSION => SourceVersionCommand::SIZE,
+        LC_NOTE => NoteCommand::SIZE,
+        _ => LoadCommand::SIZE,
+    }
+}
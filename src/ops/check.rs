@@ -0,0 +1,794 @@
+use std::fmt;
+use std::io::SeekFrom;
+
+use super::codesign::segment_page_size;
+use super::read_mach_header;
+use crate::arch::{arch_name, SliceRange};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::utils::{get_segname, load_command_name};
+
+/// A structural rule that dyld enforces and a slice breaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Short name of the rule, e.g. `cmdsize-alignment`
+    pub rule: &'static str,
+    /// What breaks the rule, e.g. a segment or load command name, which unlike the message doesn't
+    /// change when the load commands are patched
+    pub subject: String,
+    pub message: String,
+}
+
+impl Issue {
+    /// Whether `other` is the same issue, possibly at another offset
+    pub fn same_as(&self, other: &Issue) -> bool {
+        self.rule == other.rule && self.subject == other.subject
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+/// The issues found in a slice
+#[derive(Debug, Clone)]
+pub struct SliceLint {
+    pub arch: String,
+    pub range: SliceRange,
+    pub issues: Vec<Issue>,
+}
+
+struct Segment {
+    index: u32,
+    name: String,
+    vmaddr: u64,
+    vmsize: u64,
+    fileoff: u64,
+    filesize: u64,
+    sections: Vec<Section64>,
+}
+
+#[derive(Default)]
+struct Lint {
+    issues: Vec<Issue>,
+    segments: Vec<Segment>,
+    signature: Option<LinkeditDataCommand>,
+}
+
+impl Lint {
+    fn issue(&mut self, rule: &'static str, subject: String, message: String) {
+        self.issues.push(Issue {
+            rule,
+            subject,
+            message,
+        });
+    }
+
+    /// Decode a `T` at the start of the command, `None` with an issue if the command is too small
+    fn parse<T: MachOStruct>(&mut self, index: u32, command: &[u8], endian: Endian) -> Option<T> {
+        if command.len() < T::SIZE {
+            let cmd = LoadCommand::from_bytes(command, endian).cmd;
+            self.issue(
+                "cmdsize",
+                load_command_name(cmd),
+                format!(
+                    "Load command {} ({}) is {} bytes, smaller than the {} bytes of its type",
+                    index,
+                    load_command_name(cmd),
+                    command.len(),
+                    T::SIZE
+                ),
+            );
+            return None;
+        }
+        Some(T::from_bytes(command, endian))
+    }
+
+    /// Check that the string at `str_offset` starts after the fixed part of the command and is
+    /// NUL-terminated within it
+    fn check_str(
+        &mut self,
+        index: u32,
+        cmd: u32,
+        command: &[u8],
+        str_offset: u32,
+        fixed_len: usize,
+    ) {
+        let name = load_command_name(cmd);
+        let str_offset = str_offset as usize;
+        if str_offset < fixed_len || str_offset >= command.len() {
+            self.issue(
+                "name-offset",
+                name.clone(),
+                format!(
+                    "Load command {} ({}) has its string at offset {}, outside of the command ({} bytes)",
+                    index,
+                    name,
+                    str_offset,
+                    command.len()
+                ),
+            );
+        } else if !command[str_offset..].contains(&0) {
+            self.issue(
+                "string-terminated",
+                name.clone(),
+                format!(
+                    "The string of load command {} ({}) is not NUL-terminated",
+                    index, name
+                ),
+            );
+        }
+    }
+
+    fn command(&mut self, index: u32, cmd: u32, command: &[u8], endian: Endian) {
+        match cmd {
+            LC_ID_DYLIB | LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB
+            | LC_LOAD_UPWARD_DYLIB => {
+                if let Some(dylib) = self.parse::<DylibCommand>(index, command, endian) {
+                    self.check_str(
+                        index,
+                        cmd,
+                        command,
+                        dylib.dylib.name_offset,
+                        DylibCommand::SIZE,
+                    );
+                }
+            }
+            LC_RPATH => {
+                if let Some(rpath) = self.parse::<RpathCommand>(index, command, endian) {
+                    self.check_str(index, cmd, command, rpath.path_offset, RpathCommand::SIZE);
+                }
+            }
+            LC_LOAD_DYLINKER | LC_ID_DYLINKER | LC_DYLD_ENVIRONMENT => {
+                if let Some(dylinker) = self.parse::<DylinkerCommand>(index, command, endian) {
+                    self.check_str(
+                        index,
+                        cmd,
+                        command,
+                        dylinker.name_offset,
+                        DylinkerCommand::SIZE,
+                    );
+                }
+            }
+            LC_SEGMENT => {
+                if let Some(segment) = self.parse::<SegmentCommand>(index, command, endian) {
+                    let sections = self
+                        .sections::<Section>(
+                            index,
+                            &get_segname(&segment.segname),
+                            command,
+                            SegmentCommand::SIZE,
+                            segment.nsects,
+                            endian,
+                        )
+                        .into_iter()
+                        .map(|section| Section64 {
+                            sectname: section.sectname,
+                            segname: section.segname,
+                            addr: section.addr as u64,
+                            size: section.size as u64,
+                            offset: section.offset,
+                            align: section.align,
+                            reloff: section.reloff,
+                            nreloc: section.nreloc,
+                            flags: section.flags,
+                            reserved1: section.reserved1,
+                            reserved2: section.reserved2,
+                            reserved3: 0,
+                        })
+                        .collect();
+                    self.segments.push(Segment {
+                        index,
+                        name: get_segname(&segment.segname),
+                        vmaddr: segment.vmaddr as u64,
+                        vmsize: segment.vmsize as u64,
+                        fileoff: segment.fileoff as u64,
+                        filesize: segment.filesize as u64,
+                        sections,
+                    });
+                }
+            }
+            LC_SEGMENT_64 => {
+                if let Some(segment) = self.parse::<SegmentCommand64>(index, command, endian) {
+                    let sections = self.sections::<Section64>(
+                        index,
+                        &get_segname(&segment.segname),
+                        command,
+                        SegmentCommand64::SIZE,
+                        segment.nsects,
+                        endian,
+                    );
+                    self.segments.push(Segment {
+                        index,
+                        name: get_segname(&segment.segname),
+                        vmaddr: segment.vmaddr,
+                        vmsize: segment.vmsize,
+                        fileoff: segment.fileoff,
+                        filesize: segment.filesize,
+                        sections,
+                    });
+                }
+            }
+            LC_CODE_SIGNATURE => {
+                self.signature = self.parse::<LinkeditDataCommand>(index, command, endian);
+            }
+            _ => (),
+        }
+    }
+
+    /// The sections following a segment command, as many as fit if `nsects` don't
+    fn sections<T: MachOStruct>(
+        &mut self,
+        index: u32,
+        segname: &str,
+        command: &[u8],
+        fixed_len: usize,
+        nsects: u32,
+        endian: Endian,
+    ) -> Vec<T> {
+        let room = (command.len() - fixed_len) / T::SIZE;
+        if nsects as usize > room {
+            self.issue(
+                "cmdsize",
+                segname.to_string(),
+                format!(
+                    "Segment {} (load command {}) has {} sections, but its cmdsize only has room for {}",
+                    segname, index, nsects, room
+                ),
+            );
+        }
+        (0..room.min(nsects as usize))
+            .map(|i| T::from_bytes(&command[fixed_len + i * T::SIZE..], endian))
+            .collect()
+    }
+
+    /// The rules about segments, __LINKEDIT and the signature, once all commands have been read
+    fn segments(&mut self, slice_size: u64, commands_end: u64) {
+        let segments = std::mem::take(&mut self.segments);
+
+        for segment in &segments {
+            if segment.fileoff.saturating_add(segment.filesize) > slice_size {
+                self.issue(
+                    "segment-bounds",
+                    segment.name.clone(),
+                    format!(
+                        "Segment {} (load command {}) runs past the end of the slice",
+                        segment.name, segment.index
+                    ),
+                );
+            }
+        }
+        for (i, a) in segments.iter().enumerate() {
+            for b in &segments[i + 1..] {
+                let overlap = |a_start: u64, a_size: u64, b_start: u64, b_size: u64| {
+                    a_size > 0
+                        && b_size > 0
+                        && a_start < b_start.saturating_add(b_size)
+                        && b_start < a_start.saturating_add(a_size)
+                };
+                if overlap(a.fileoff, a.filesize, b.fileoff, b.filesize) {
+                    self.issue(
+                        "segment-overlap",
+                        format!("{} {} (file)", a.name, b.name),
+                        format!("Segments {} and {} overlap in the file", a.name, b.name),
+                    );
+                }
+                if overlap(a.vmaddr, a.vmsize, b.vmaddr, b.vmsize) {
+                    self.issue(
+                        "segment-overlap",
+                        format!("{} {} (memory)", a.name, b.name),
+                        format!("Segments {} and {} overlap in memory", a.name, b.name),
+                    );
+                }
+            }
+        }
+
+        let linkedit = segments.iter().find(|segment| segment.name == "__LINKEDIT");
+        if let Some(linkedit) = linkedit {
+            let after = segments.iter().find(|segment| {
+                segment.index != linkedit.index
+                    && ((segment.filesize > 0 && segment.fileoff > linkedit.fileoff)
+                        || segment.vmaddr > linkedit.vmaddr)
+            });
+            if let Some(after) = after {
+                self.issue(
+                    "linkedit-last",
+                    after.name.clone(),
+                    format!(
+                        "__LINKEDIT is not the last segment, {} comes after it",
+                        after.name
+                    ),
+                );
+            }
+        }
+
+        if let Some(signature) = self.signature.take() {
+            let start = signature.dataoff as u64;
+            let end = start + signature.datasize as u64;
+            match linkedit {
+                None => self.issue(
+                    "signature-in-linkedit",
+                    "LC_CODE_SIGNATURE".to_string(),
+                    "LC_CODE_SIGNATURE is present, but there is no __LINKEDIT segment".to_string(),
+                ),
+                Some(linkedit)
+                    if start < linkedit.fileoff
+                        || end > linkedit.fileoff.saturating_add(linkedit.filesize) =>
+                {
+                    self.issue(
+                        "signature-in-linkedit",
+                        "LC_CODE_SIGNATURE".to_string(),
+                        format!(
+                            "The code signature ({:#x}..{:#x}) is not inside __LINKEDIT ({:#x}..{:#x})",
+                            start,
+                            end,
+                            linkedit.fileoff,
+                            linkedit.fileoff.saturating_add(linkedit.filesize)
+                        ),
+                    )
+                }
+                Some(_) => (),
+            }
+        }
+
+        let first_section = segments
+            .iter()
+            .flat_map(|segment| &segment.sections)
+            .filter(|section| {
+                let section_type = section.flags & SECTION_TYPE;
+                section.offset != 0
+                    && section.size > 0
+                    && !matches!(
+                        section_type,
+                        S_ZEROFILL | S_GB_ZEROFILL | S_THREAD_LOCAL_ZEROFILL
+                    )
+            })
+            .min_by_key(|section| section.offset);
+        if let Some(section) = first_section {
+            if commands_end > section.offset as u64 {
+                self.issue(
+                    "commands-overlap-sections",
+                    format!(
+                        "{},{}",
+                        get_segname(&section.segname),
+                        get_segname(&section.sectname)
+                    ),
+                    format!(
+                        "The load commands end at {:#x}, past the start of section {},{} at {:#x}",
+                        commands_end,
+                        get_segname(&section.segname),
+                        get_segname(&section.sectname),
+                        section.offset
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Check the load commands of the slice in `range` against the rules dyld enforces
+///
+/// Unlike `check_slice`, a malformed slice is not an error but reported as issues, as far as the
+/// load commands can be read.
+pub fn lint_slice<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    range: SliceRange,
+) -> Result<SliceLint> {
+    let (mach_header, endian) = read_mach_header(binary_file, range.offset)?;
+    let mut lint = Lint::default();
+
+    let mut commands_len = mach_header.sizeofcmds as u64;
+    let available = range.size.saturating_sub(mach_header.len());
+    if commands_len > available {
+        lint.issue(
+            "load-commands-bounds",
+            "load commands".to_string(),
+            format!(
+                "The load commands ({} bytes) run past the end of the slice",
+                commands_len
+            ),
+        );
+        commands_len = available;
+    }
+    let mut commands = vec![0u8; commands_len as usize];
+    binary_file.seek(SeekFrom::Start(range.offset + mach_header.len()))?;
+    binary_file.read_exact(&mut commands)?;
+
+    let align = match mach_header.is_64_bit() {
+        true => 8,
+        false => 4,
+    };
+    let mut pos = 0usize;
+    let mut complete = true;
+    for index in 0..mach_header.ncmds {
+        if pos + LoadCommand::SIZE > commands.len() {
+            lint.issue(
+                "load-commands-bounds",
+                "load commands".to_string(),
+                format!(
+                    "ncmds is {}, but load command {} starts past the end of sizeofcmds",
+                    mach_header.ncmds, index
+                ),
+            );
+            complete = false;
+            break;
+        }
+        let lc = LoadCommand::from_bytes(&commands[pos..], endian);
+        let cmdsize = lc.cmdsize as usize;
+        if cmdsize < LoadCommand::SIZE || pos + cmdsize > commands.len() {
+            lint.issue(
+                "cmdsize",
+                load_command_name(lc.cmd),
+                format!(
+                    "Load command {} ({}) has cmdsize {}, which runs past the end of sizeofcmds",
+                    index,
+                    load_command_name(lc.cmd),
+                    lc.cmdsize
+                ),
+            );
+            complete = false;
+            break;
+        }
+        if lc.cmdsize % align != 0 {
+            lint.issue(
+                "cmdsize-alignment",
+                load_command_name(lc.cmd),
+                format!(
+                    "Load command {} ({}) has cmdsize {}, which is not a multiple of {}",
+                    index,
+                    load_command_name(lc.cmd),
+                    lc.cmdsize,
+                    align
+                ),
+            );
+        }
+        lint.command(index, lc.cmd, &commands[pos..pos + cmdsize], endian);
+        pos += cmdsize;
+    }
+    if complete && pos as u64 != commands_len {
+        lint.issue(
+            "load-commands-bounds",
+            "load commands".to_string(),
+            format!(
+                "The load commands take {} bytes, but sizeofcmds is {}",
+                pos, mach_header.sizeofcmds
+            ),
+        );
+    }
+    lint.segments(
+        range.size,
+        mach_header.len() + mach_header.sizeofcmds as u64,
+    );
+
+    Ok(SliceLint {
+        arch: arch_name(mach_header.cputype, mach_header.cpusubtype),
+        range,
+        issues: lint.issues,
+    })
+}
+
+/// Check every slice of a thin or fat binary, and the alignment of the slices of a fat binary
+///
+/// Also returns whether the binary is fat, like `read_slice_ranges`.
+pub fn lint_binary<F: InsertDylibFileExt>(binary_file: &mut F) -> Result<(Vec<SliceLint>, bool)> {
+    binary_file.seek(SeekFrom::Start(0))?;
+    let mut magic_buffer = [0u8; 4];
+    binary_file.fpeek(&mut magic_buffer)?;
+    let magic = u32::from_be_bytes(magic_buffer);
+
+    match magic {
+        MH_CIGAM_64 | MH_MAGIC_64 | MH_CIGAM | MH_MAGIC => {
+            let range = SliceRange {
+                offset: 0,
+                size: binary_file.flen()?,
+            };
+            Ok((vec![lint_slice(binary_file, range)?], false))
+        }
+        FAT_MAGIC | FAT_CIGAM => {
+            let fat_endian = match magic {
+                FAT_MAGIC => Endian::Big,
+                _ => Endian::Little,
+            };
+            // unlike `read_fat_archs`, slices out of the file are reported rather than an error
+            let file_len = binary_file.flen()?;
+            let fh: FatHeader = binary_file.fpeek_struct(fat_endian)?;
+            let archs_end = FatHeader::SIZE as u64 + fh.nfat_arch as u64 * FatArch::SIZE as u64;
+            if archs_end > file_len {
+                return Err(InsertDylibError::TooManyArchs(fh.nfat_arch));
+            }
+            let mut fat_archs = Vec::new();
+            binary_file.seek(SeekFrom::Start(FatHeader::SIZE as u64))?;
+            for _ in 0..fh.nfat_arch {
+                fat_archs.push(binary_file.fpeek_struct::<FatArch>(fat_endian)?);
+                binary_file.seek(SeekFrom::Current(FatArch::SIZE as i64))?;
+            }
+
+            let mut slices = Vec::new();
+            for fat_arch in fat_archs {
+                let range = SliceRange {
+                    offset: fat_arch.offset as u64,
+                    size: fat_arch.size as u64,
+                };
+                if range.offset + range.size > file_len {
+                    slices.push(SliceLint {
+                        arch: arch_name(fat_arch.cputype, fat_arch.cpusubtype),
+                        range,
+                        issues: vec![Issue {
+                            rule: "slice-bounds",
+                            subject: "slice".to_string(),
+                            message: format!(
+                                "The slice at {:#x} ({:#x} bytes) runs past the end of the file",
+                                range.offset, range.size
+                            ),
+                        }],
+                    });
+                    continue;
+                }
+                let mut slice = lint_slice(binary_file, range)?;
+                let page_size = segment_page_size(fat_arch.cputype);
+                let alignment = 1u64.checked_shl(fat_arch.align).unwrap_or(0);
+                if alignment == 0 || !range.offset.is_multiple_of(alignment) {
+                    slice.issues.push(Issue {
+                        rule: "fat-alignment",
+                        subject: "slice".to_string(),
+                        message: format!(
+                            "The slice at {:#x} is not aligned to 2^{} as its fat arch says",
+                            range.offset, fat_arch.align
+                        ),
+                    });
+                } else if !range.offset.is_multiple_of(page_size) {
+                    slice.issues.push(Issue {
+                        rule: "fat-alignment",
+                        subject: "slice".to_string(),
+                        message: format!(
+                            "The slice at {:#x} is not aligned to the page size {:#x}",
+                            range.offset, page_size
+                        ),
+                    });
+                }
+                slices.push(slice);
+            }
+            Ok((slices, true))
+        }
+        _ => Err(InsertDylibError::BadMagic(magic)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const FILE_LEN: usize = 0x1100;
+
+    fn name16(name: &str) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    fn segment(
+        name: &str,
+        vmaddr: u64,
+        vmsize: u64,
+        fileoff: u64,
+        filesize: u64,
+        sections: &[Section64],
+    ) -> Vec<u8> {
+        let mut command = SegmentCommand64 {
+            cmd: LC_SEGMENT_64,
+            cmdsize: (SegmentCommand64::SIZE + sections.len() * Section64::SIZE) as u32,
+            segname: name16(name),
+            vmaddr,
+            vmsize,
+            fileoff,
+            filesize,
+            maxprot: 7,
+            initprot: 5,
+            nsects: sections.len() as u32,
+            flags: 0,
+        }
+        .to_bytes(Endian::Little);
+        for section in sections {
+            command.extend(section.to_bytes(Endian::Little));
+        }
+        command
+    }
+
+    fn text_section(offset: u32) -> Section64 {
+        Section64 {
+            sectname: name16("__text"),
+            segname: name16("__TEXT"),
+            addr: 0x1000 + offset as u64,
+            size: 0x100,
+            offset,
+            ..Default::default()
+        }
+    }
+
+    /// An LC_LOAD_DYLIB with `name` as is, unpadded
+    fn dylib(name: &[u8]) -> Vec<u8> {
+        let mut command = DylibCommand {
+            cmd: LC_LOAD_DYLIB,
+            cmdsize: (DylibCommand::SIZE + name.len()) as u32,
+            dylib: Dylib {
+                name_offset: DylibCommand::SIZE as u32,
+                ..Default::default()
+            },
+        }
+        .to_bytes(Endian::Little);
+        command.extend_from_slice(name);
+        command
+    }
+
+    const LIBZ: &[u8; 24] = b"/usr/lib/libz.dylib\0\0\0\0\0";
+
+    /// The load commands of a well-formed slice with __LINKEDIT at 0x1000
+    fn commands() -> Vec<Vec<u8>> {
+        vec![
+            segment("__PAGEZERO", 0, 0x1000, 0, 0, &[]),
+            segment("__TEXT", 0x1000, 0x1000, 0, 0x1000, &[text_section(0x800)]),
+            segment("__LINKEDIT", 0x2000, 0x1000, 0x1000, 0x100, &[]),
+            dylib(LIBZ),
+        ]
+    }
+
+    fn binary(commands: &[Vec<u8>]) -> Vec<u8> {
+        let mut binary = MachHeader {
+            magic: MH_MAGIC_64,
+            cputype: CPU_TYPE_X86_64,
+            cpusubtype: 3,
+            filetype: MH_EXECUTE,
+            ncmds: commands.len() as u32,
+            sizeofcmds: commands.iter().map(Vec::len).sum::<usize>() as u32,
+            flags: 0,
+            reserved: 0,
+        }
+        .to_bytes(Endian::Little);
+        binary.extend(commands.concat());
+        binary.resize(FILE_LEN, 0);
+        binary
+    }
+
+    fn set_ncmds(binary: &mut [u8], ncmds: u32) {
+        binary[16..20].copy_from_slice(&ncmds.to_le_bytes());
+    }
+
+    /// The rule and subject of every issue in the slice
+    fn lint(binary: Vec<u8>) -> Vec<(&'static str, String)> {
+        let range = SliceRange {
+            offset: 0,
+            size: binary.len() as u64,
+        };
+        lint_slice(&mut Cursor::new(binary), range)
+            .unwrap()
+            .issues
+            .into_iter()
+            .map(|issue| (issue.rule, issue.subject))
+            .collect()
+    }
+
+    fn issue(rule: &'static str, subject: &str) -> Vec<(&'static str, String)> {
+        vec![(rule, subject.to_string())]
+    }
+
+    #[test]
+    fn well_formed_slice() {
+        assert_eq!(lint(binary(&commands())), vec![]);
+    }
+
+    #[test]
+    fn load_commands_bounds() {
+        let mut bad = binary(&commands());
+        set_ncmds(&mut bad, 5);
+        assert_eq!(lint(bad), issue("load-commands-bounds", "load commands"));
+
+        let mut bad = binary(&commands());
+        set_ncmds(&mut bad, 3);
+        assert_eq!(lint(bad), issue("load-commands-bounds", "load commands"));
+    }
+
+    #[test]
+    fn cmdsize() {
+        let mut too_many_sections = commands();
+        // room for the one section only
+        too_many_sections[1][64..68].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(lint(binary(&too_many_sections)), issue("cmdsize", "__TEXT"));
+
+        let mut too_small = commands();
+        too_small[3].truncate(16);
+        too_small[3][4..8].copy_from_slice(&16u32.to_le_bytes());
+        assert_eq!(lint(binary(&too_small)), issue("cmdsize", "LC_LOAD_DYLIB"));
+    }
+
+    #[test]
+    fn cmdsize_alignment() {
+        let mut commands = commands();
+        commands[3] = dylib(b"/a\0\0");
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("cmdsize-alignment", "LC_LOAD_DYLIB")
+        );
+    }
+
+    #[test]
+    fn name_offset() {
+        let mut commands = commands();
+        commands[3][8..12].copy_from_slice(&100u32.to_le_bytes());
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("name-offset", "LC_LOAD_DYLIB")
+        );
+    }
+
+    #[test]
+    fn string_terminated() {
+        let mut commands = commands();
+        commands[3] = dylib(b"/usr/lib/libz.dylib12345");
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("string-terminated", "LC_LOAD_DYLIB")
+        );
+    }
+
+    #[test]
+    fn segment_bounds() {
+        let mut commands = commands();
+        commands[2] = segment("__LINKEDIT", 0x2000, 0x1000, 0x1000, 0x200, &[]);
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("segment-bounds", "__LINKEDIT")
+        );
+    }
+
+    #[test]
+    fn segment_overlap() {
+        let mut commands = commands();
+        commands[1] = segment("__TEXT", 0x1000, 0x2000, 0, 0x1000, &[text_section(0x800)]);
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("segment-overlap", "__TEXT __LINKEDIT (memory)")
+        );
+    }
+
+    #[test]
+    fn linkedit_last() {
+        let mut commands = commands();
+        commands.push(segment("__DATA", 0x3000, 0x1000, 0, 0, &[]));
+        assert_eq!(lint(binary(&commands)), issue("linkedit-last", "__DATA"));
+    }
+
+    #[test]
+    fn signature_in_linkedit() {
+        let mut commands = commands();
+        commands.push(
+            LinkeditDataCommand {
+                cmd: LC_CODE_SIGNATURE,
+                cmdsize: LinkeditDataCommand::SIZE as u32,
+                dataoff: 0x800,
+                datasize: 0x10,
+            }
+            .to_bytes(Endian::Little),
+        );
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("signature-in-linkedit", "LC_CODE_SIGNATURE")
+        );
+    }
+
+    #[test]
+    fn commands_overlap_sections() {
+        let mut commands = commands();
+        commands[1] = segment("__TEXT", 0x1000, 0x1000, 0, 0x1000, &[text_section(0x40)]);
+        assert_eq!(
+            lint(binary(&commands)),
+            issue("commands-overlap-sections", "__TEXT,__text")
+        );
+    }
+}
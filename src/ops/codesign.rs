@@ -0,0 +1,462 @@
+use std::fmt;
+use std::io::SeekFrom;
+
+use sha2::{Digest, Sha256};
+
+use super::{has_free_space, read_mach_header, write_mach_header};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+use crate::utils::{get_segname, round_up_u64};
+
+/// Page size the code directory hashes, as a power of 2
+const CODE_PAGE_SHIFT: u8 = 12;
+const CODE_PAGE_SIZE: u64 = 1 << CODE_PAGE_SHIFT;
+const SHA256_SIZE: u64 = 32;
+/// Special slots in the code directory: the Info.plist (-1) and the requirements (-2)
+const N_SPECIAL_SLOTS: u32 = 2;
+const REQUIREMENTS_SIZE: u32 = 12;
+
+pub(super) struct Segment {
+    pub command_pos: u64,
+    pub cmd: u32,
+    pub fileoff: u64,
+    pub filesize: u64,
+}
+
+/// The load commands that the code signature depends on
+#[derive(Default)]
+pub(super) struct SignatureCommands {
+    pub linkedit: Option<Segment>,
+    pub text: Option<Segment>,
+    pub symtab_pos: Option<u64>,
+    /// Position of LC_CODE_SIGNATURE, the command itself and whether it is the last command
+    pub signature: Option<(u64, LinkeditDataCommand, bool)>,
+}
+
+pub(super) fn read_signature_commands<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+) -> Result<SignatureCommands> {
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut commands = SignatureCommands::default();
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+        let command_pos = binary_file.ftello()?;
+
+        match lc.cmd {
+            LC_SEGMENT | LC_SEGMENT_64 => {
+                let (segname, fileoff, filesize) = match lc.cmd {
+                    LC_SEGMENT => {
+                        let cmd: SegmentCommand = binary_file.fpeek_struct(endian)?;
+                        (cmd.segname, cmd.fileoff as u64, cmd.filesize as u64)
+                    }
+                    _ => {
+                        let cmd: SegmentCommand64 = binary_file.fpeek_struct(endian)?;
+                        (cmd.segname, cmd.fileoff, cmd.filesize)
+                    }
+                };
+                let segment = Segment {
+                    command_pos,
+                    cmd: lc.cmd,
+                    fileoff,
+                    filesize,
+                };
+                match get_segname(&segname).as_str() {
+                    "__LINKEDIT" => commands.linkedit = Some(segment),
+                    "__TEXT" => commands.text = Some(segment),
+                    _ => (),
+                }
+            }
+            LC_SYMTAB => commands.symtab_pos = Some(command_pos),
+            LC_CODE_SIGNATURE => {
+                let cmd: LinkeditDataCommand = binary_file.fpeek_struct(endian)?;
+                commands.signature = Some((command_pos, cmd, i + 1 == mach_header.ncmds));
+            }
+            _ => (),
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    Ok(commands)
+}
+
+/// Set the file size of the segment at `segment.command_pos`, rounding its vm size up to `page_size`
+pub(super) fn resize_segment<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    segment: &Segment,
+    endian: Endian,
+    filesize: u64,
+    page_size: u64,
+) -> Result<()> {
+    let vmsize = round_up_u64(filesize, page_size);
+
+    binary_file.seek(SeekFrom::Start(segment.command_pos))?;
+    if segment.cmd == LC_SEGMENT {
+        let mut cmd: SegmentCommand = binary_file.fpeek_struct(endian)?;
+        cmd.filesize = filesize as u32;
+        cmd.vmsize = vmsize as u32;
+        binary_file.write_all(&cmd.to_bytes(endian))?;
+    } else {
+        let mut cmd: SegmentCommand64 = binary_file.fpeek_struct(endian)?;
+        cmd.filesize = filesize;
+        cmd.vmsize = vmsize;
+        binary_file.write_all(&cmd.to_bytes(endian))?;
+    }
+
+    Ok(())
+}
+
+/// Whether and how a slice is signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureState {
+    Unsigned,
+    /// Signed without a certificate, like `codesign -s -`, with the identifier of the code directory
+    AdHoc(String),
+    /// Signed with a certificate, with the identifier of the code directory
+    Signed(String),
+    /// LC_CODE_SIGNATURE points to something that is not a signature with a code directory
+    Invalid,
+}
+
+impl fmt::Display for SignatureState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureState::Unsigned => write!(f, "none"),
+            SignatureState::AdHoc(identifier) => write!(f, "ad-hoc ({})", identifier),
+            SignatureState::Signed(identifier) => write!(f, "signed ({})", identifier),
+            SignatureState::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+/// Read the code directory of the slice at `header_offset` to tell how it is signed
+pub fn read_signature_state<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+) -> Result<SignatureState> {
+    let cmd = match read_signature_commands(binary_file, header_offset)?.signature {
+        Some((_, cmd, _)) => cmd,
+        None => return Ok(SignatureState::Unsigned),
+    };
+    let signature_start = header_offset + cmd.dataoff as u64;
+    if signature_start + cmd.datasize as u64 > binary_file.flen()? {
+        return Ok(SignatureState::Invalid);
+    }
+    let mut signature = vec![0u8; cmd.datasize as usize];
+    binary_file.seek(SeekFrom::Start(signature_start))?;
+    binary_file.read_exact(&mut signature)?;
+
+    let blob = |offset: usize, size: usize| signature.get(offset..offset.checked_add(size)?);
+    let super_blob = match blob(0, SuperBlob::SIZE) {
+        Some(bytes) => SuperBlob::from_bytes(bytes, Endian::Big),
+        None => return Ok(SignatureState::Invalid),
+    };
+    if super_blob.magic != CSMAGIC_EMBEDDED_SIGNATURE {
+        return Ok(SignatureState::Invalid);
+    }
+    for index in 0..super_blob.count as usize {
+        let index = match blob(SuperBlob::SIZE + index * BlobIndex::SIZE, BlobIndex::SIZE) {
+            Some(bytes) => BlobIndex::from_bytes(bytes, Endian::Big),
+            None => break,
+        };
+        if index.blob_type != CSSLOT_CODEDIRECTORY {
+            continue;
+        }
+        let code_directory = match blob(index.offset as usize, CodeDirectory::SIZE) {
+            Some(bytes) => CodeDirectory::from_bytes(bytes, Endian::Big),
+            None => break,
+        };
+        if code_directory.magic != CSMAGIC_CODEDIRECTORY {
+            break;
+        }
+        let identifier = signature
+            .get(index.offset as usize + code_directory.ident_offset as usize..)
+            .unwrap_or_default();
+        let identifier = identifier
+            .split(|byte| *byte == 0)
+            .next()
+            .unwrap_or_default();
+        let identifier = String::from_utf8_lossy(identifier).into_owned();
+        return Ok(match code_directory.flags & CS_ADHOC {
+            0 => SignatureState::Signed(identifier),
+            _ => SignatureState::AdHoc(identifier),
+        });
+    }
+
+    Ok(SignatureState::Invalid)
+}
+
+/// What `strip_code_signature` did to a slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripOutcome {
+    Stripped,
+    NotSigned,
+    /// LC_CODE_SIGNATURE is not the last load command and was left alone, the slice is still signed
+    Skipped,
+}
+
+/// Remove LC_CODE_SIGNATURE from the slice at `header_offset`
+///
+/// If the signature is at the end of __LINKEDIT and of the slice, __LINKEDIT and the string table
+/// are shrunk and so is `slice_size`, otherwise the signature data is only zeroed. Fails with
+/// `SignatureNotLast` if LC_CODE_SIGNATURE is not the last load command, unless `all_yes` is set,
+/// in which case the signature is skipped with a warning.
+pub fn strip_code_signature<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    all_yes: bool,
+    slice_size: &mut u64,
+    reporter: &mut Reporter,
+) -> Result<StripOutcome> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+    let commands = read_signature_commands(binary_file, header_offset)?;
+
+    let (command_pos, cmd, last) = match commands.signature {
+        Some(signature) => signature,
+        None => return Ok(StripOutcome::NotSigned),
+    };
+    if !last {
+        if !all_yes {
+            return Err(InsertDylibError::SignatureNotLast);
+        }
+        reporter.warning("LC_CODE_SIGNATURE is not the last load command, so couldn't remove.");
+        return Ok(StripOutcome::Skipped);
+    }
+
+    binary_file.fbzero(command_pos, cmd.cmdsize as u64)?;
+
+    let mut shrunk = false;
+    match &commands.linkedit {
+        None => reporter.warning("__LINKEDIT segment not found."),
+        Some(linkedit) if linkedit.fileoff + linkedit.filesize != *slice_size => {
+            reporter.warning("__LINKEDIT segment is not at the end of the file, so codesign will not work on the patched binary.");
+        }
+        Some(linkedit)
+            if cmd.dataoff as u64 + cmd.datasize as u64 != *slice_size
+                || (cmd.dataoff as u64) < linkedit.fileoff =>
+        {
+            reporter.warning("Codesignature is not at the end of __LINKEDIT segment, so codesign will not work on the patched binary.");
+        }
+        Some(linkedit) => {
+            *slice_size -= cmd.datasize as u64;
+
+            match commands.symtab_pos {
+                None => reporter.warning("LC_SYMTAB load command not found. codesign might not work on the patched binary."),
+                Some(symtab_pos) => {
+                    binary_file.seek(SeekFrom::Start(symtab_pos))?;
+                    let mut symtab: SymtabCommand = binary_file.fpeek_struct(endian)?;
+                    let diffsize =
+                        symtab.stroff as i64 + symtab.strsize as i64 - (*slice_size as i64);
+                    if (-16..=0).contains(&diffsize) {
+                        symtab.strsize = ((symtab.strsize as i32) - (diffsize as i32)) as u32;
+
+                        binary_file.write_all(&symtab.to_bytes(endian))?;
+                    } else {
+                        reporter.warning(format!("String table doesn't appear right before code signature. codesign might not work on the patched binary. {:016x}", diffsize));
+                    }
+                }
+            }
+
+            let linkedit_filesize = linkedit.filesize - cmd.datasize as u64;
+            resize_segment(binary_file, linkedit, endian, linkedit_filesize, 0x1000)?;
+
+            shrunk = true;
+        }
+    }
+
+    if !shrunk {
+        binary_file.fbzero(header_offset + cmd.dataoff as u64, cmd.datasize as u64)?;
+    }
+
+    mach_header.ncmds -= 1;
+    mach_header.sizeofcmds -= cmd.cmdsize;
+    write_mach_header(binary_file, header_offset, &mach_header, endian)?;
+
+    Ok(StripOutcome::Stripped)
+}
+
+/// Length of the signature blob for `code_limit` bytes of code
+fn signature_size(code_limit: u64, identifier: &str) -> u64 {
+    let n_code_slots = code_limit.div_ceil(CODE_PAGE_SIZE);
+    let code_directory_size = CodeDirectory::SIZE as u64
+        + identifier.len() as u64
+        + 1
+        + (N_SPECIAL_SLOTS as u64 + n_code_slots) * SHA256_SIZE;
+    SuperBlob::SIZE as u64
+        + 2 * BlobIndex::SIZE as u64
+        + code_directory_size
+        + REQUIREMENTS_SIZE as u64
+}
+
+/// Upper bound of how much `sign_adhoc` grows a slice of `slice_size` bytes
+pub fn signature_reserve(slice_size: u64, identifier: &str) -> u64 {
+    // the signature starts 16-byte aligned
+    16 + round_up_u64(signature_size(slice_size + 16, identifier), 16)
+}
+
+/// Page size of the segments of a slice for `cputype`
+pub(super) fn segment_page_size(cputype: u32) -> u64 {
+    match cputype {
+        CPU_TYPE_ARM64 => 0x4000,
+        _ => 0x1000,
+    }
+}
+
+/// Sign the slice at `header_offset` ad-hoc, replacing an existing signature
+///
+/// Like `codesign -s -`, the signature consists of a SHA-256 code directory identified as
+/// `identifier` and empty requirements. It is appended to __LINKEDIT, which must be at the end of
+/// the slice, and `slice_size` grows accordingly.
+pub fn sign_adhoc<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    identifier: &str,
+    slice_size: &mut u64,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    if strip_code_signature(binary_file, header_offset, false, slice_size, reporter)?
+        == StripOutcome::Stripped
+    {
+        reporter.note("Replacing the existing code signature.");
+    }
+
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+    let commands = read_signature_commands(binary_file, header_offset)?;
+    let linkedit = match commands.linkedit {
+        Some(linkedit) if linkedit.fileoff + linkedit.filesize == *slice_size => linkedit,
+        Some(_) => {
+            return Err(InsertDylibError::Unsignable(
+                "__LINKEDIT segment is not at the end of the file".to_string(),
+            ))
+        }
+        None => {
+            return Err(InsertDylibError::Unsignable(
+                "__LINKEDIT segment not found".to_string(),
+            ))
+        }
+    };
+
+    let cmdsize = LinkeditDataCommand::SIZE as u32;
+    let commands_end = header_offset + mach_header.len() + mach_header.sizeofcmds as u64;
+    binary_file.seek(SeekFrom::Start(commands_end))?;
+    if mach_header.len() + mach_header.sizeofcmds as u64 + cmdsize as u64 > *slice_size
+        || !has_free_space(binary_file, cmdsize as u64)?
+    {
+        return Err(InsertDylibError::NoSpace {
+            needed: cmdsize as u64,
+        });
+    }
+
+    let code_limit = round_up_u64(*slice_size, 16);
+    let blob_size = signature_size(code_limit, identifier);
+    let datasize = round_up_u64(blob_size, 16);
+
+    // the load commands and __LINKEDIT are hashed, so they must be final before hashing
+    let signature_command = LinkeditDataCommand {
+        cmd: LC_CODE_SIGNATURE,
+        cmdsize,
+        dataoff: code_limit as u32,
+        datasize: datasize as u32,
+    };
+    binary_file.write_all(&signature_command.to_bytes(endian))?;
+    mach_header.ncmds += 1;
+    mach_header.sizeofcmds += cmdsize;
+    write_mach_header(binary_file, header_offset, &mach_header, endian)?;
+
+    resize_segment(
+        binary_file,
+        &linkedit,
+        endian,
+        code_limit + datasize - linkedit.fileoff,
+        segment_page_size(mach_header.cputype),
+    )?;
+    binary_file.fbzero(header_offset + *slice_size, code_limit - *slice_size)?;
+
+    let mut code_hashes: Vec<u8> = Vec::new();
+    let mut page = vec![0u8; CODE_PAGE_SIZE as usize];
+    binary_file.seek(SeekFrom::Start(header_offset))?;
+    let mut hashed = 0u64;
+    while hashed < code_limit {
+        let page_len = std::cmp::min(CODE_PAGE_SIZE, code_limit - hashed) as usize;
+        binary_file.read_exact(&mut page[..page_len])?;
+        code_hashes.extend_from_slice(&Sha256::digest(&page[..page_len]));
+        hashed += page_len as u64;
+    }
+
+    let requirements = SuperBlob {
+        magic: CSMAGIC_REQUIREMENTS,
+        length: REQUIREMENTS_SIZE,
+        count: 0,
+    }
+    .to_bytes(Endian::Big);
+
+    let ident_offset = CodeDirectory::SIZE as u32;
+    let hash_offset =
+        ident_offset + identifier.len() as u32 + 1 + N_SPECIAL_SLOTS * SHA256_SIZE as u32;
+    let (exec_seg_base, exec_seg_limit) = match &commands.text {
+        Some(text) => (text.fileoff, text.filesize),
+        None => (0, 0),
+    };
+    let code_directory = CodeDirectory {
+        magic: CSMAGIC_CODEDIRECTORY,
+        length: hash_offset + code_hashes.len() as u32,
+        version: 0x20400,
+        flags: CS_ADHOC,
+        hash_offset,
+        ident_offset,
+        n_special_slots: N_SPECIAL_SLOTS,
+        n_code_slots: (code_hashes.len() as u64 / SHA256_SIZE) as u32,
+        code_limit: code_limit as u32,
+        hash_size: SHA256_SIZE as u8,
+        hash_type: CS_HASHTYPE_SHA256,
+        page_size: CODE_PAGE_SHIFT,
+        exec_seg_base,
+        exec_seg_limit,
+        exec_seg_flags: match mach_header.filetype {
+            MH_EXECUTE => CS_EXECSEG_MAIN_BINARY,
+            _ => 0,
+        },
+        ..Default::default()
+    };
+
+    let mut signature = FieldWriter::new(Endian::Big);
+    let code_directory_offset = (SuperBlob::SIZE + 2 * BlobIndex::SIZE) as u32;
+    SuperBlob {
+        magic: CSMAGIC_EMBEDDED_SIGNATURE,
+        length: blob_size as u32,
+        count: 2,
+    }
+    .write_fields(&mut signature);
+    BlobIndex {
+        blob_type: CSSLOT_CODEDIRECTORY,
+        offset: code_directory_offset,
+    }
+    .write_fields(&mut signature);
+    BlobIndex {
+        blob_type: CSSLOT_REQUIREMENTS,
+        offset: code_directory_offset + code_directory.length,
+    }
+    .write_fields(&mut signature);
+    code_directory.write_fields(&mut signature);
+    signature.bytes(identifier.as_bytes());
+    signature.u8(0);
+    // special slots are stored in reverse order right before the code slots
+    signature.bytes(&Sha256::digest(&requirements));
+    signature.bytes(&[0u8; SHA256_SIZE as usize]);
+    signature.bytes(&code_hashes);
+    signature.bytes(&requirements);
+
+    binary_file.seek(SeekFrom::Start(header_offset + code_limit))?;
+    binary_file.write_all(&signature.into_bytes())?;
+    binary_file.fbzero(header_offset + code_limit + blob_size, datasize - blob_size)?;
+
+    *slice_size = code_limit + datasize;
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::io::SeekFrom;
+
+use super::{read_dylib_name, read_lc_str, read_mach_header, remove_load_command};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+use crate::utils::load_command_name;
+
+struct DylibLoad {
+    command_pos: u64,
+    cmd: u32,
+    cmdsize: u32,
+    /// `None` if the path can't be read, the command then only holds its ordinal
+    path: Option<String>,
+    /// Spellings this dylib is known under, see `dylib_keys`
+    keys: Vec<String>,
+}
+
+impl DylibLoad {
+    /// Path to print, only duplicates and the commands they duplicate are printed and those
+    /// always have one
+    fn path(&self) -> &str {
+        self.path.as_deref().unwrap_or_default()
+    }
+}
+
+/// Remove load commands that load a dylib which is already loaded by an earlier command
+///
+/// Paths are compared after normalizing `//` and `./`, and, if `resolve_rpath` is set, after
+/// resolving `@rpath/` against every LC_RPATH. The first load command for a dylib is kept, so its
+/// ordinal doesn't change. Removing a duplicate shifts the ordinals of the dylibs loaded after it,
+/// which is refused unless `all_yes` is set. LC_LAZY_LOAD_DYLIB commands count towards the ordinals
+/// but are never removed.
+pub fn dedupe_dylibs<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    resolve_rpath: bool,
+    all_yes: bool,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut dylibs: Vec<DylibLoad> = Vec::new();
+    let mut rpaths: Vec<String> = Vec::new();
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        match lc.cmd {
+            LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LOAD_UPWARD_DYLIB
+            | LC_LAZY_LOAD_DYLIB => {
+                let path = match read_dylib_name(binary_file, lc.cmdsize, endian)? {
+                    Ok(path) => Some(path),
+                    Err(e) => {
+                        reporter.warning(format!(
+                            "Cannot get dylib path for load command at {}: {}",
+                            i, e
+                        ));
+                        None
+                    }
+                };
+                // keep unreadable commands too, so that indices in `dylibs` stay ordinals
+                dylibs.push(DylibLoad {
+                    command_pos: binary_file.ftello()?,
+                    cmd: lc.cmd,
+                    cmdsize: lc.cmdsize,
+                    path,
+                    keys: Vec::new(),
+                });
+            }
+            LC_RPATH => {
+                let rpath_command: RpathCommand = binary_file.fpeek_struct(endian)?;
+                match read_lc_str(binary_file, lc.cmdsize, rpath_command.path_offset)? {
+                    Ok(rpath) => rpaths.push(rpath),
+                    Err(e) => reporter
+                        .warning(format!("Cannot get rpath for load command at {}: {}", i, e)),
+                }
+            }
+            _ => (),
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    // lazily loaded dylibs only hold their ordinal, without keys they never match another command
+    for dylib in dylibs
+        .iter_mut()
+        .filter(|dylib| dylib.cmd != LC_LAZY_LOAD_DYLIB)
+    {
+        if let Some(path) = &dylib.path {
+            dylib.keys = dylib_keys(path, &rpaths, resolve_rpath);
+        }
+    }
+
+    // index of every duplicate and of the load command it duplicates
+    let mut duplicates: Vec<(usize, usize)> = Vec::new();
+    for (index, dylib) in dylibs.iter().enumerate() {
+        let survivor = dylibs[..index]
+            .iter()
+            .enumerate()
+            .filter(|(earlier, _)| !duplicates.iter().any(|(dup, _)| dup == earlier))
+            .find(|(_, earlier)| earlier.keys.iter().any(|key| dylib.keys.contains(key)));
+        if let Some((survivor, _)) = survivor {
+            duplicates.push((index, survivor));
+        }
+    }
+
+    if duplicates.is_empty() {
+        reporter.note("No duplicate dylib load commands found.");
+        return Ok(());
+    }
+
+    for (duplicate, survivor) in &duplicates {
+        reporter.note(format!(
+            "Removing {} {} (ordinal {}), already loaded as {} (ordinal {})",
+            load_command_name(dylibs[*duplicate].cmd),
+            dylibs[*duplicate].path(),
+            duplicate + 1,
+            dylibs[*survivor].path(),
+            survivor + 1
+        ));
+        if dylibs[*duplicate].cmd == LC_LOAD_DYLIB && dylibs[*survivor].cmd != LC_LOAD_DYLIB {
+            reporter.warning(format!(
+                "{} is kept as {}",
+                dylibs[*survivor].path(),
+                load_command_name(dylibs[*survivor].cmd)
+            ));
+        }
+    }
+
+    let first_duplicate = duplicates[0].0;
+    let shifted = (first_duplicate..dylibs.len())
+        .filter(|index| !duplicates.iter().any(|(dup, _)| dup == index))
+        .count();
+    if shifted > 0 {
+        reporter.warning(format!(
+            "removing the duplicates changes the ordinals of {} dylib(s) loaded after them, symbols bound to those ordinals will resolve to the wrong dylib.",
+            shifted
+        ));
+        if !all_yes {
+            reporter.note("Pass --all-yes to remove them anyway.");
+            return Err(InsertDylibError::OrdinalsShifted(shifted));
+        }
+    }
+
+    for (duplicate, _) in duplicates.iter().rev() {
+        let dylib = &dylibs[*duplicate];
+        remove_load_command(
+            binary_file,
+            &mut mach_header,
+            endian,
+            header_offset,
+            dylib.command_pos,
+            dylib.cmdsize,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Normalize `path` by collapsing repeated `/` and dropping `.` components
+pub fn normalize_path(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect();
+
+    let mut normalized = components.join("/");
+    if absolute {
+        normalized.insert(0, '/');
+    }
+    normalized
+}
+
+/// All normalized spellings of `path`, including its `@rpath/` resolutions if `resolve_rpath` is set
+fn dylib_keys(path: &str, rpaths: &[String], resolve_rpath: bool) -> Vec<String> {
+    let mut keys = vec![normalize_path(path)];
+    if resolve_rpath {
+        if let Some(rest) = path.strip_prefix("@rpath/") {
+            for rpath in rpaths {
+                keys.push(normalize_path(&format!("{}/{}", rpath, rest)));
+            }
+        }
+    }
+    keys
+}
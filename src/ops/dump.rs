@@ -0,0 +1,387 @@
+use std::fmt;
+use std::io::SeekFrom;
+
+use super::{read_lc_str, read_mach_header};
+use crate::arch::{format_version, platform_name};
+use crate::error::Result;
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::utils::{format_source_version, format_uuid, get_segname, load_command_name, tool_name};
+
+/// The fields of a load command, decoded according to its type
+#[derive(Debug)]
+pub enum CommandDetail {
+    /// LC_SEGMENT or LC_SEGMENT_64, 32-bit segments and sections are widened to 64 bits
+    Segment {
+        command: SegmentCommand64,
+        sections: Vec<Section64>,
+    },
+    Symtab(SymtabCommand),
+    Dysymtab(DysymtabCommand),
+    /// Any command that points to a blob in __LINKEDIT, e.g. LC_CODE_SIGNATURE
+    LinkeditData(LinkeditDataCommand),
+    Dylib {
+        command: DylibCommand,
+        path: String,
+    },
+    Dylinker(String),
+    Rpath(String),
+    Uuid([u8; 16]),
+    BuildVersion {
+        command: BuildVersionCommand,
+        tools: Vec<BuildToolVersion>,
+    },
+    VersionMin(VersionMinCommand),
+    SourceVersion(SourceVersionCommand),
+    EntryPoint(EntryPointCommand),
+    EncryptionInfo(EncryptionInfoCommand),
+    DyldInfo(DyldInfoCommand),
+    Note(NoteCommand),
+    /// A command without a decoder, only `cmd` and `cmdsize` are known
+    Unknown,
+}
+
+/// A decoded load command
+#[derive(Debug)]
+pub struct LoadCommandDump {
+    pub index: u32,
+    /// Offset of the command from the start of the slice
+    pub offset: u64,
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub detail: CommandDetail,
+}
+
+/// Decode every load command of the slice at `header_offset`
+///
+/// The slice must have passed `check_slice`, so that every command is at least as large as the
+/// struct it is decoded into.
+pub fn dump_load_commands<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+) -> Result<Vec<LoadCommandDump>> {
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut commands = Vec::new();
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for index in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+        let command_pos = binary_file.ftello()?;
+
+        let detail = read_command_detail(binary_file, &lc, endian)?;
+        commands.push(LoadCommandDump {
+            index,
+            offset: command_pos - header_offset,
+            cmd: lc.cmd,
+            cmdsize: lc.cmdsize,
+            detail,
+        });
+
+        binary_file.seek(SeekFrom::Start(command_pos + lc.cmdsize as u64))?;
+    }
+
+    Ok(commands)
+}
+
+/// Decode the load command `lc` at the file cursor, which may be left anywhere inside the command
+fn read_command_detail<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    lc: &LoadCommand,
+    endian: Endian,
+) -> Result<CommandDetail> {
+    let read_str = |binary_file: &mut F, str_offset: u32| -> Result<String> {
+        Ok(match read_lc_str(binary_file, lc.cmdsize, str_offset)? {
+            Ok(s) => s,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        })
+    };
+
+    let detail = match lc.cmd {
+        LC_SEGMENT => {
+            let cmd: SegmentCommand = binary_file.fpeek_struct(endian)?;
+            let nsects = cmd
+                .nsects
+                .min((lc.cmdsize - SegmentCommand::SIZE as u32) / Section::SIZE as u32);
+            binary_file.seek(SeekFrom::Current(SegmentCommand::SIZE as i64))?;
+            let mut sections = Vec::new();
+            for _ in 0..nsects {
+                let section: Section = binary_file.fpeek_struct(endian)?;
+                binary_file.seek(SeekFrom::Current(Section::SIZE as i64))?;
+                sections.push(Section64 {
+                    sectname: section.sectname,
+                    segname: section.segname,
+                    addr: section.addr as u64,
+                    size: section.size as u64,
+                    offset: section.offset,
+                    align: section.align,
+                    reloff: section.reloff,
+                    nreloc: section.nreloc,
+                    flags: section.flags,
+                    reserved1: section.reserved1,
+                    reserved2: section.reserved2,
+                    reserved3: 0,
+                });
+            }
+            CommandDetail::Segment {
+                command: SegmentCommand64 {
+                    cmd: cmd.cmd,
+                    cmdsize: cmd.cmdsize,
+                    segname: cmd.segname,
+                    vmaddr: cmd.vmaddr as u64,
+                    vmsize: cmd.vmsize as u64,
+                    fileoff: cmd.fileoff as u64,
+                    filesize: cmd.filesize as u64,
+                    maxprot: cmd.maxprot,
+                    initprot: cmd.initprot,
+                    nsects: cmd.nsects,
+                    flags: cmd.flags,
+                },
+                sections,
+            }
+        }
+        LC_SEGMENT_64 => {
+            let command: SegmentCommand64 = binary_file.fpeek_struct(endian)?;
+            let nsects = command
+                .nsects
+                .min((lc.cmdsize - SegmentCommand64::SIZE as u32) / Section64::SIZE as u32);
+            binary_file.seek(SeekFrom::Current(SegmentCommand64::SIZE as i64))?;
+            let mut sections = Vec::new();
+            for _ in 0..nsects {
+                sections.push(binary_file.fpeek_struct(endian)?);
+                binary_file.seek(SeekFrom::Current(Section64::SIZE as i64))?;
+            }
+            CommandDetail::Segment { command, sections }
+        }
+        LC_SYMTAB => CommandDetail::Symtab(binary_file.fpeek_struct(endian)?),
+        LC_DYSYMTAB => CommandDetail::Dysymtab(binary_file.fpeek_struct(endian)?),
+        LC_CODE_SIGNATURE
+        | LC_SEGMENT_SPLIT_INFO
+        | LC_FUNCTION_STARTS
+        | LC_DATA_IN_CODE
+        | LC_DYLIB_CODE_SIGN_DRS
+        | LC_LINKER_OPTIMIZATION_HINT
+        | LC_DYLD_EXPORTS_TRIE
+        | LC_DYLD_CHAINED_FIXUPS => CommandDetail::LinkeditData(binary_file.fpeek_struct(endian)?),
+        LC_ID_DYLIB | LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB
+        | LC_LOAD_UPWARD_DYLIB => {
+            let command: DylibCommand = binary_file.fpeek_struct(endian)?;
+            let path = read_str(binary_file, command.dylib.name_offset)?;
+            CommandDetail::Dylib { command, path }
+        }
+        LC_LOAD_DYLINKER | LC_ID_DYLINKER | LC_DYLD_ENVIRONMENT => {
+            let command: DylinkerCommand = binary_file.fpeek_struct(endian)?;
+            CommandDetail::Dylinker(read_str(binary_file, command.name_offset)?)
+        }
+        LC_RPATH => {
+            let command: RpathCommand = binary_file.fpeek_struct(endian)?;
+            CommandDetail::Rpath(read_str(binary_file, command.path_offset)?)
+        }
+        LC_UUID => {
+            let command: UuidCommand = binary_file.fpeek_struct(endian)?;
+            CommandDetail::Uuid(command.uuid)
+        }
+        LC_BUILD_VERSION => {
+            let command: BuildVersionCommand = binary_file.fpeek_struct(endian)?;
+            let ntools = command.ntools.min(
+                (lc.cmdsize - BuildVersionCommand::SIZE as u32) / BuildToolVersion::SIZE as u32,
+            );
+            binary_file.seek(SeekFrom::Current(BuildVersionCommand::SIZE as i64))?;
+            let mut tools = Vec::new();
+            for _ in 0..ntools {
+                tools.push(binary_file.fpeek_struct(endian)?);
+                binary_file.seek(SeekFrom::Current(BuildToolVersion::SIZE as i64))?;
+            }
+            CommandDetail::BuildVersion { command, tools }
+        }
+        LC_VERSION_MIN_MACOSX
+        | LC_VERSION_MIN_IPHONEOS
+        | LC_VERSION_MIN_TVOS
+        | LC_VERSION_MIN_WATCHOS => CommandDetail::VersionMin(binary_file.fpeek_struct(endian)?),
+        LC_SOURCE_VERSION => CommandDetail::SourceVersion(binary_file.fpeek_struct(endian)?),
+        LC_MAIN => CommandDetail::EntryPoint(binary_file.fpeek_struct(endian)?),
+        LC_ENCRYPTION_INFO | LC_ENCRYPTION_INFO_64 => {
+            CommandDetail::EncryptionInfo(binary_file.fpeek_struct(endian)?)
+        }
+        LC_DYLD_INFO | LC_DYLD_INFO_ONLY => {
+            CommandDetail::DyldInfo(binary_file.fpeek_struct(endian)?)
+        }
+        LC_NOTE => CommandDetail::Note(binary_file.fpeek_struct(endian)?),
+        _ => CommandDetail::Unknown,
+    };
+
+    Ok(detail)
+}
+
+/// A decoded field as `(name, value)`, named and formatted like `otool -l`
+pub type Field = (&'static str, String);
+
+impl LoadCommandDump {
+    /// The fields following `cmd` and `cmdsize` in `otool -l` order, without the sections of a
+    /// segment
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields = Vec::new();
+        let mut field =
+            |name: &'static str, value: &dyn fmt::Display| fields.push((name, value.to_string()));
+
+        match &self.detail {
+            CommandDetail::Segment { command, .. } => {
+                field("segname", &get_segname(&command.segname));
+                field("vmaddr", &format!("{:#018x}", command.vmaddr));
+                field("vmsize", &format!("{:#018x}", command.vmsize));
+                field("fileoff", &command.fileoff);
+                field("filesize", &command.filesize);
+                field("maxprot", &format!("{:#010x}", command.maxprot));
+                field("initprot", &format!("{:#010x}", command.initprot));
+                field("nsects", &command.nsects);
+                field("flags", &format!("{:#x}", command.flags));
+            }
+            CommandDetail::Symtab(command) => {
+                field("symoff", &command.symoff);
+                field("nsyms", &command.nsyms);
+                field("stroff", &command.stroff);
+                field("strsize", &command.strsize);
+            }
+            CommandDetail::Dysymtab(command) => {
+                field("ilocalsym", &command.ilocalsym);
+                field("nlocalsym", &command.nlocalsym);
+                field("iextdefsym", &command.iextdefsym);
+                field("nextdefsym", &command.nextdefsym);
+                field("iundefsym", &command.iundefsym);
+                field("nundefsym", &command.nundefsym);
+                field("tocoff", &command.tocoff);
+                field("ntoc", &command.ntoc);
+                field("modtaboff", &command.modtaboff);
+                field("nmodtab", &command.nmodtab);
+                field("extrefsymoff", &command.extrefsymoff);
+                field("nextrefsyms", &command.nextrefsyms);
+                field("indirectsymoff", &command.indirectsymoff);
+                field("nindirectsyms", &command.nindirectsyms);
+                field("extreloff", &command.extreloff);
+                field("nextrel", &command.nextrel);
+                field("locreloff", &command.locreloff);
+                field("nlocrel", &command.nlocrel);
+            }
+            CommandDetail::LinkeditData(command) => {
+                field("dataoff", &command.dataoff);
+                field("datasize", &command.datasize);
+            }
+            CommandDetail::Dylib { command, path } => {
+                field(
+                    "name",
+                    &format!("{} (offset {})", path, command.dylib.name_offset),
+                );
+                field("time stamp", &command.dylib.timestamp);
+                field(
+                    "current version",
+                    &format_version(command.dylib.current_version),
+                );
+                field(
+                    "compatibility version",
+                    &format_version(command.dylib.compatibility_version),
+                );
+            }
+            CommandDetail::Dylinker(name) => field("name", &name),
+            CommandDetail::Rpath(path) => field("path", &path),
+            CommandDetail::Uuid(uuid) => field("uuid", &format_uuid(uuid)),
+            CommandDetail::BuildVersion { command, tools } => {
+                field("platform", &platform_name(command.platform));
+                field("minos", &format_version(command.minos));
+                field("sdk", &format_version(command.sdk));
+                field("ntools", &command.ntools);
+                for tool in tools {
+                    field("tool", &tool_name(tool.tool));
+                    field("version", &format_version(tool.version));
+                }
+            }
+            CommandDetail::VersionMin(command) => {
+                field("version", &format_version(command.version));
+                field("sdk", &format_version(command.sdk));
+            }
+            CommandDetail::SourceVersion(command) => {
+                field("version", &format_source_version(command.version));
+            }
+            CommandDetail::EntryPoint(command) => {
+                field("entryoff", &command.entryoff);
+                field("stacksize", &command.stacksize);
+            }
+            CommandDetail::EncryptionInfo(command) => {
+                field("cryptoff", &command.cryptoff);
+                field("cryptsize", &command.cryptsize);
+                field("cryptid", &command.cryptid);
+            }
+            CommandDetail::DyldInfo(command) => {
+                field("rebase_off", &command.rebase_off);
+                field("rebase_size", &command.rebase_size);
+                field("bind_off", &command.bind_off);
+                field("bind_size", &command.bind_size);
+                field("weak_bind_off", &command.weak_bind_off);
+                field("weak_bind_size", &command.weak_bind_size);
+                field("lazy_bind_off", &command.lazy_bind_off);
+                field("lazy_bind_size", &command.lazy_bind_size);
+                field("export_off", &command.export_off);
+                field("export_size", &command.export_size);
+            }
+            CommandDetail::Note(command) => {
+                field("data_owner", &get_segname(&command.data_owner));
+                field("offset", &command.offset);
+                field("size", &command.size);
+            }
+            CommandDetail::Unknown => (),
+        }
+
+        fields
+    }
+
+    /// The fields of every section of a segment
+    pub fn sections(&self) -> Vec<Vec<Field>> {
+        match &self.detail {
+            CommandDetail::Segment { sections, .. } => {
+                sections.iter().map(section_fields).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn section_fields(section: &Section64) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let mut field =
+        |name: &'static str, value: &dyn fmt::Display| fields.push((name, value.to_string()));
+
+    field("sectname", &get_segname(&section.sectname));
+    field("segname", &get_segname(&section.segname));
+    field("addr", &format!("{:#018x}", section.addr));
+    field("size", &format!("{:#018x}", section.size));
+    field("offset", &section.offset);
+    field(
+        "align",
+        &format!("2^{} ({})", section.align, 1u64 << section.align.min(63)),
+    );
+    field("reloff", &section.reloff);
+    field("nreloc", &section.nreloc);
+    field("flags", &format!("{:#010x}", section.flags));
+    field("reserved1", &section.reserved1);
+    field("reserved2", &section.reserved2);
+    fields
+}
+
+impl fmt::Display for LoadCommandDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Load command {}", self.index)?;
+        writeln!(f, "{:>14} {}", "cmd", load_command_name(self.cmd))?;
+        writeln!(f, "{:>14} {}", "cmdsize", self.cmdsize)?;
+        for (name, value) in self.fields() {
+            writeln!(f, "{:>14} {}", name, value)?;
+        }
+        for section in self.sections() {
+            writeln!(f, "Section")?;
+            for (name, value) in section {
+                writeln!(f, "{:>14} {}", name, value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::SeekFrom;
+
+use super::{dylib_command_size, grow_load_command, read_dylib_name, read_mach_header};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+
+/// Change the install name in LC_ID_DYLIB to `install_name`
+pub fn set_install_name<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    install_name: &str,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut id_dylib = None;
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for _ in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        if lc.cmd == LC_ID_DYLIB {
+            id_dylib = Some((binary_file.ftello()?, lc));
+            break;
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    let (command_pos, lc) = match id_dylib {
+        Some(id_dylib) => id_dylib,
+        None => return Err(InsertDylibError::NotADylib),
+    };
+
+    if let Ok(name) = read_dylib_name(binary_file, lc.cmdsize, endian)? {
+        if name.eq(install_name) {
+            reporter.note(format!("Install name is already {}.", install_name));
+            return Ok(());
+        }
+    }
+
+    let dylib_command: DylibCommand = binary_file.fpeek_struct(endian)?;
+
+    rewrite_dylib_name(
+        binary_file,
+        &mut mach_header,
+        endian,
+        header_offset,
+        command_pos,
+        dylib_command,
+        install_name,
+    )
+}
+
+/// Rewrite the dylib command at `command_pos` so that it refers to `name`
+///
+/// The command keeps its size if the new name fits, otherwise it grows into the empty space after
+/// the load commands.
+pub fn rewrite_dylib_name<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    mach_header: &mut MachHeader,
+    endian: Endian,
+    header_offset: u64,
+    command_pos: u64,
+    mut dylib_command: DylibCommand,
+    name: &str,
+) -> Result<()> {
+    let old_cmdsize = dylib_command.cmdsize;
+    let new_cmdsize = dylib_command_size(name).max(old_cmdsize);
+    grow_load_command(
+        binary_file,
+        mach_header,
+        endian,
+        header_offset,
+        command_pos,
+        old_cmdsize,
+        new_cmdsize,
+    )?;
+
+    dylib_command.cmdsize = new_cmdsize;
+    dylib_command.dylib.name_offset = DylibCommand::SIZE as u32;
+
+    let mut command = dylib_command.to_bytes(endian);
+    command.extend_from_slice(name.as_bytes());
+    command.resize(new_cmdsize as usize, 0);
+
+    binary_file.seek(SeekFrom::Start(command_pos))?;
+    binary_file.write_all(&command)?;
+
+    Ok(())
+}
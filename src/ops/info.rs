@@ -0,0 +1,76 @@
+use std::io::SeekFrom;
+
+use super::{read_dylib_name, read_mach_header};
+use crate::arch::{read_slice_arch, SliceArch, SliceRange};
+use crate::error::Result;
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+
+/// Summary of a single Mach-O slice
+#[derive(Debug)]
+pub struct SliceInfo {
+    pub range: SliceRange,
+    pub arch: SliceArch,
+    pub filetype: u32,
+    pub ncmds: u32,
+    pub sizeofcmds: u32,
+    /// Zero bytes right after the load commands, i.e. how much the load commands can grow
+    pub free_space: u64,
+    /// Install name from LC_ID_DYLIB
+    pub install_name: Option<String>,
+    pub signed: bool,
+}
+
+/// Summarize the slice at `range`
+pub fn read_slice_info<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    range: SliceRange,
+) -> Result<SliceInfo> {
+    let header_offset = range.offset;
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut install_name = None;
+    let mut signed = false;
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for _ in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        match lc.cmd {
+            LC_ID_DYLIB => install_name = read_dylib_name(binary_file, lc.cmdsize, endian)?.ok(),
+            LC_CODE_SIGNATURE => signed = true,
+            _ => (),
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    // count the zero bytes after the load commands, up to the first section
+    let commands_end = mach_header.len() + mach_header.sizeofcmds as u64;
+    binary_file.seek(SeekFrom::Start(header_offset + commands_end))?;
+    let mut free_space = 0u64;
+    let mut buffer = [0u8; 512];
+    while commands_end + free_space < range.size {
+        let len = std::cmp::min(buffer.len() as u64, range.size - commands_end - free_space);
+        let buffer = &mut buffer[..len as usize];
+        binary_file.read_exact(buffer)?;
+        match buffer.iter().position(|byte| *byte != 0) {
+            Some(zeros) => {
+                free_space += zeros as u64;
+                break;
+            }
+            None => free_space += len,
+        }
+    }
+
+    Ok(SliceInfo {
+        range,
+        arch: read_slice_arch(binary_file, header_offset)?,
+        filetype: mach_header.filetype,
+        ncmds: mach_header.ncmds,
+        sizeofcmds: mach_header.sizeofcmds,
+        free_space,
+        install_name,
+        signed,
+    })
+}
@@ -0,0 +1,155 @@
+use std::io::SeekFrom;
+
+use super::codesign::{strip_code_signature, StripOutcome};
+use super::{
+    dylib_command_size, has_free_space, read_dylib_name, read_mach_header, write_mach_header,
+};
+use crate::arch::{check_compatibility, read_slice_arch, SliceArch};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::opts::Opts;
+use crate::report::Reporter;
+
+/// Add a load command for `options.dylib_path` after the existing load commands
+///
+/// LC_CODE_SIGNATURE is stripped first if requested, which may shrink `slice_size`.
+pub fn insert_dylib<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    slice_size: &mut u64,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    if !dylib_archs.is_empty() {
+        let target = read_slice_arch(binary_file, header_offset)?;
+        match check_compatibility(&target, dylib_archs) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    reporter.warning(warning);
+                }
+            }
+            Err(reason) => {
+                if !options.all_yes {
+                    return Err(InsertDylibError::ArchMismatch(reason));
+                }
+                reporter.warning(format!("Architecture mismatch: {}", reason));
+                reporter.note("Continuing anyway because of --all-yes.");
+            }
+        }
+    }
+
+    if options.strip_codesign
+        && strip_code_signature(
+            binary_file,
+            header_offset,
+            options.all_yes,
+            slice_size,
+            reporter,
+        )? == StripOutcome::Stripped
+    {
+        // stripping updated the header in the file
+        mach_header = read_mach_header(binary_file, header_offset)?.0;
+    }
+
+    let commands_offset = header_offset + mach_header.len();
+    check_load_commands(
+        binary_file,
+        &mach_header,
+        endian,
+        commands_offset,
+        options,
+        reporter,
+    )?;
+
+    let cmdsize = dylib_command_size(&options.dylib_path);
+
+    let dylib_command = DylibCommand {
+        cmd: match options.weak {
+            true => LC_LOAD_WEAK_DYLIB,
+            false => LC_LOAD_DYLIB,
+        },
+        cmdsize,
+        dylib: Dylib {
+            name_offset: DylibCommand::SIZE as u32,
+            timestamp: 0,
+            current_version: 0,
+            compatibility_version: 0,
+        },
+    };
+
+    // not even --all-yes may write the new command past the end of the slice
+    if mach_header.len() + mach_header.sizeofcmds as u64 + cmdsize as u64 > *slice_size {
+        return Err(InsertDylibError::NoSpace {
+            needed: cmdsize as u64,
+        });
+    }
+
+    binary_file.seek(SeekFrom::Start(
+        commands_offset + mach_header.sizeofcmds as u64,
+    ))?;
+    if !has_free_space(binary_file, cmdsize as u64)? {
+        if !options.all_yes {
+            return Err(InsertDylibError::NoSpace {
+                needed: cmdsize as u64,
+            });
+        }
+        reporter
+            .warning("It doesn't seem like there is enough empty space. Will continue though...");
+    }
+
+    binary_file.write_all(&dylib_command.to_bytes(endian))?;
+    binary_file.write_all(options.dylib_path.as_ref())?;
+
+    mach_header.ncmds += 1;
+    mach_header.sizeofcmds += cmdsize;
+
+    write_mach_header(binary_file, header_offset, &mach_header, endian)?;
+
+    Ok(())
+}
+
+/// Check that the binary doesn't load `options.dylib_path` already
+fn check_load_commands<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    mach_header: &MachHeader,
+    endian: Endian,
+    commands_offset: u64,
+    options: &Opts,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    binary_file.seek(SeekFrom::Start(commands_offset))?;
+
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        if lc.cmd == LC_LOAD_DYLIB || lc.cmd == LC_LOAD_WEAK_DYLIB {
+            let name = match read_dylib_name(binary_file, lc.cmdsize, endian)? {
+                Ok(name) => name,
+                Err(e) => {
+                    reporter.warning(format!(
+                        "Cannot get dylib path for load command at {}: {}",
+                        i, e
+                    ));
+                    binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+                    continue;
+                }
+            };
+
+            if name.eq(&options.dylib_path) {
+                if !options.all_yes {
+                    return Err(InsertDylibError::AlreadyPresent(name));
+                }
+                reporter.warning("Binary already contains a load command for that dylib. Continuing anyway because of --all-yes.");
+            }
+        }
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    Ok(())
+}
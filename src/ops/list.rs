@@ -0,0 +1,78 @@
+use std::io::SeekFrom;
+
+use super::{read_dylib_name, read_lc_str, read_mach_header};
+use crate::error::Result;
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+
+/// A load command naming a dylib, either one that is loaded or the slice's own LC_ID_DYLIB
+#[derive(Debug)]
+pub struct DylibEntry {
+    pub cmd: u32,
+    /// `None` if the path can't be read, the entry is still listed so that it keeps its ordinal
+    pub path: Option<String>,
+    pub current_version: u32,
+    pub compatibility_version: u32,
+}
+
+/// Dylibs and run path search paths of a single slice
+#[derive(Debug, Default)]
+pub struct Dependencies {
+    /// In load command order, so the index of a loaded dylib is its ordinal minus 1, apart from
+    /// LC_ID_DYLIB
+    pub dylibs: Vec<DylibEntry>,
+    pub rpaths: Vec<String>,
+}
+
+/// List the dylibs and rpaths of the slice at `header_offset`, in load command order
+pub fn list_dependencies<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    reporter: &mut Reporter,
+) -> Result<Dependencies> {
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut dependencies = Dependencies::default();
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        match lc.cmd {
+            LC_ID_DYLIB | LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB
+            | LC_LOAD_UPWARD_DYLIB | LC_LAZY_LOAD_DYLIB => {
+                let dylib_command: DylibCommand = binary_file.fpeek_struct(endian)?;
+                let path = match read_dylib_name(binary_file, lc.cmdsize, endian)? {
+                    Ok(path) => Some(path),
+                    Err(e) => {
+                        reporter.warning(format!(
+                            "Cannot get dylib path for load command at {}: {}",
+                            i, e
+                        ));
+                        None
+                    }
+                };
+                dependencies.dylibs.push(DylibEntry {
+                    cmd: lc.cmd,
+                    path,
+                    current_version: dylib_command.dylib.current_version,
+                    compatibility_version: dylib_command.dylib.compatibility_version,
+                });
+            }
+            LC_RPATH => {
+                let rpath_command: RpathCommand = binary_file.fpeek_struct(endian)?;
+                match read_lc_str(binary_file, lc.cmdsize, rpath_command.path_offset)? {
+                    Ok(rpath) => dependencies.rpaths.push(rpath),
+                    Err(e) => reporter
+                        .warning(format!("Cannot get rpath for load command at {}: {}", i, e)),
+                }
+            }
+            _ => (),
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    Ok(dependencies)
+}
@@ -0,0 +1,185 @@
+pub mod bounds;
+pub mod check;
+pub mod codesign;
+pub mod dedupe;
+pub mod dump;
+pub mod id;
+pub mod info;
+pub mod insert;
+pub mod list;
+pub mod provenance;
+pub mod remove;
+pub mod rewrite;
+pub mod weak;
+
+use std::io;
+use std::io::SeekFrom;
+use std::string::FromUtf8Error;
+
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::utils::round_up_u64;
+
+/// Read the Mach-O header at `header_offset` along with the byte order of its slice
+pub fn read_mach_header<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+) -> Result<(MachHeader, Endian)> {
+    binary_file.seek(SeekFrom::Start(header_offset))?;
+
+    let mut header_buffer = [0u8; MachHeader::SIZE];
+    binary_file.read_exact(&mut header_buffer)?;
+
+    let magic = [
+        header_buffer[0],
+        header_buffer[1],
+        header_buffer[2],
+        header_buffer[3],
+    ];
+    match Endian::from_magic(magic) {
+        Some(endian) => Ok((MachHeader::from_bytes(&header_buffer, endian), endian)),
+        None => Err(InsertDylibError::BadMagic(u32::from_be_bytes(magic))),
+    }
+}
+
+/// Write `mach_header` back to `header_offset`
+pub fn write_mach_header<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    mach_header: &MachHeader,
+    endian: Endian,
+) -> io::Result<()> {
+    binary_file.seek(SeekFrom::Start(header_offset))?;
+    binary_file.write_all(&mach_header.to_bytes(endian))
+}
+
+/// Size of a load command made of `fixed_len` bytes followed by `path`, including the NUL terminator and padding
+pub fn lc_str_command_size(fixed_len: u32, path: &str) -> u32 {
+    round_up_u64(fixed_len as u64 + path.len() as u64 + 1, 8) as u32
+}
+
+/// Size of a dylib command holding `dylib_path`, including the NUL terminator and padding
+pub fn dylib_command_size(dylib_path: &str) -> u32 {
+    lc_str_command_size(DylibCommand::SIZE as u32, dylib_path)
+}
+
+/// Check that the next `len` bytes from the file cursor are all zeros, leaving the cursor untouched
+///
+/// Used on the space right after the load commands to tell whether they can grow into it.
+pub fn has_free_space<F: InsertDylibFileExt>(binary_file: &mut F, len: u64) -> io::Result<bool> {
+    let mut space: Vec<u8> = vec![0; len as usize];
+    binary_file.fpeek(&mut space[..])?;
+    Ok(space.iter().all(|item| *item == 0))
+}
+
+/// Read the string stored at `str_offset` in the load command at the current file cursor,
+/// leaving the cursor untouched
+pub fn read_lc_str<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    cmdsize: u32,
+    str_offset: u32,
+) -> io::Result<Result<String, FromUtf8Error>> {
+    let mut name_buffer: Vec<u8> = vec![0; cmdsize as usize];
+    binary_file.fpeek(&mut name_buffer)?;
+
+    let name_start: usize = str_offset as usize;
+    let name_max_index = cmdsize as usize;
+    let mut name_end = name_max_index;
+
+    for (index, buf) in name_buffer
+        .iter()
+        .enumerate()
+        .take(name_max_index)
+        .skip(name_start)
+    {
+        if *buf == 0 {
+            name_end = index;
+            break;
+        }
+    }
+
+    // `check_slice` has validated `str_offset` already, but don't panic on a bogus one anyway
+    let name = name_buffer.get(name_start..name_end).unwrap_or_default();
+    Ok(String::from_utf8(name.to_vec()))
+}
+
+/// Read the path of the dylib command at the current file cursor, leaving the cursor untouched
+pub fn read_dylib_name<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    cmdsize: u32,
+    endian: Endian,
+) -> io::Result<Result<String, FromUtf8Error>> {
+    let dylib_command: DylibCommand = binary_file.fpeek_struct(endian)?;
+
+    read_lc_str(binary_file, cmdsize, dylib_command.dylib.name_offset)
+}
+
+/// Grow the load command at `command_pos` from `old_cmdsize` to `new_cmdsize` bytes
+///
+/// The load commands behind it are moved into the empty space after the load commands and
+/// `sizeofcmds` is updated in the header. The content of the resized command is left to the caller.
+/// Fails with `NoSpace` without touching the file if there is not enough empty space.
+pub fn grow_load_command<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    mach_header: &mut MachHeader,
+    endian: Endian,
+    header_offset: u64,
+    command_pos: u64,
+    old_cmdsize: u32,
+    new_cmdsize: u32,
+) -> Result<()> {
+    if new_cmdsize <= old_cmdsize {
+        return Ok(());
+    }
+
+    let commands_end = header_offset + mach_header.len() + mach_header.sizeofcmds as u64;
+
+    let growth = new_cmdsize - old_cmdsize;
+    binary_file.seek(SeekFrom::Start(commands_end))?;
+    if !has_free_space(binary_file, growth as u64)? {
+        return Err(InsertDylibError::NoSpace {
+            needed: growth as u64,
+        });
+    }
+
+    let tail_start = command_pos + old_cmdsize as u64;
+    let mut tail: Vec<u8> = vec![0; (commands_end - tail_start) as usize];
+    binary_file.seek(SeekFrom::Start(tail_start))?;
+    binary_file.read_exact(&mut tail)?;
+    binary_file.seek(SeekFrom::Start(command_pos + new_cmdsize as u64))?;
+    binary_file.write_all(&tail)?;
+
+    mach_header.sizeofcmds += growth;
+    write_mach_header(binary_file, header_offset, mach_header, endian)?;
+
+    Ok(())
+}
+
+/// Remove the load command at `command_pos`, moving the load commands behind it forward
+///
+/// The freed bytes at the end of the load commands are zeroed and `ncmds` and `sizeofcmds` are
+/// updated in the header.
+pub fn remove_load_command<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    mach_header: &mut MachHeader,
+    endian: Endian,
+    header_offset: u64,
+    command_pos: u64,
+    cmdsize: u32,
+) -> io::Result<()> {
+    let commands_end = header_offset + mach_header.len() + mach_header.sizeofcmds as u64;
+
+    let tail_start = command_pos + cmdsize as u64;
+    let mut tail: Vec<u8> = vec![0; (commands_end - tail_start) as usize];
+    binary_file.seek(SeekFrom::Start(tail_start))?;
+    binary_file.read_exact(&mut tail)?;
+    binary_file.seek(SeekFrom::Start(command_pos))?;
+    binary_file.write_all(&tail)?;
+    binary_file.fbzero(commands_end - cmdsize as u64, cmdsize as u64)?;
+
+    mach_header.ncmds -= 1;
+    mach_header.sizeofcmds -= cmdsize;
+    write_mach_header(binary_file, header_offset, mach_header, endian)
+}
@@ -0,0 +1,258 @@
+use std::io::SeekFrom;
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::codesign::{read_signature_commands, resize_segment, segment_page_size};
+use super::{grow_load_command, read_mach_header, write_mach_header};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::ops::rewrite::RewriteRule;
+use crate::opts::{Operation, Opts};
+use crate::report::Reporter;
+use crate::utils::{get_segname, round_up_u64};
+
+/// `data_owner` of the LC_NOTE holding a provenance record
+pub const PROVENANCE_OWNER: &str = "insert_dylib_rs";
+
+/// Who changed a slice and how, as recorded by `--provenance`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub tool: String,
+    pub version: String,
+    pub operation: String,
+    pub arguments: Vec<String>,
+    /// SHA-256 of the slice before the operation, hex encoded
+    pub original_sha256: String,
+}
+
+impl Provenance {
+    /// The record for running `options` on a slice hashing to `original_sha256`
+    pub fn new(options: &Opts, original_sha256: String) -> Provenance {
+        let mut arguments = Vec::new();
+        match &options.operation {
+            Operation::Insert => {
+                arguments.push(options.dylib_path.clone());
+                if options.weak {
+                    arguments.push("--weak".to_string());
+                }
+                if options.strip_codesign {
+                    arguments.push("--strip-codesign".to_string());
+                }
+            }
+            Operation::SetWeak(_) | Operation::Remove => arguments.push(options.dylib_path.clone()),
+            Operation::SetId(install_name) => arguments.push(install_name.clone()),
+            Operation::Rewrite(rules) => arguments.extend(rules.iter().map(|rule| match rule {
+                RewriteRule::Prefix { from, to } => format!("{}={}", from, to),
+                RewriteRule::Glob {
+                    pattern,
+                    replacement,
+                } => format!("{}={}", pattern, replacement),
+            })),
+            Operation::Dedupe {
+                resolve_rpath: true,
+            } => arguments.push("--resolve-rpath".to_string()),
+            Operation::Sign { identifier } => arguments.push(identifier.clone()),
+            _ => (),
+        }
+
+        Provenance {
+            tool: PROVENANCE_OWNER.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            operation: options.operation.name().to_string(),
+            arguments,
+            original_sha256,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "tool": self.tool,
+            "version": self.version,
+            "operation": self.operation,
+            "arguments": self.arguments,
+            "original_sha256": self.original_sha256,
+        })
+    }
+
+    /// Parse a record, `None` if it isn't one
+    pub fn from_json(value: &Value) -> Option<Provenance> {
+        let string = |key: &str| value.get(key)?.as_str().map(String::from);
+        Some(Provenance {
+            tool: string("tool")?,
+            version: string("version")?,
+            operation: string("operation")?,
+            arguments: value
+                .get("arguments")?
+                .as_array()?
+                .iter()
+                .filter_map(|argument| argument.as_str().map(String::from))
+                .collect(),
+            original_sha256: string("original_sha256")?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_json().to_string().into_bytes()
+    }
+}
+
+/// Upper bound of how much `add_provenance` grows a slice for the record of `options`
+pub fn provenance_reserve(options: &Opts) -> u64 {
+    let record = Provenance::new(options, "0".repeat(2 * Sha256::output_size()));
+    round_up_u64(record.to_bytes().len() as u64, 8) + 8
+}
+
+/// SHA-256 of the slice at `header_offset`, hex encoded
+pub fn hash_slice<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    slice_size: u64,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    binary_file.seek(SeekFrom::Start(header_offset))?;
+    let mut hashed = 0u64;
+    while hashed < slice_size {
+        let len = std::cmp::min(buf.len() as u64, slice_size - hashed) as usize;
+        binary_file.read_exact(&mut buf[..len])?;
+        hasher.update(&buf[..len]);
+        hashed += len as u64;
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Append `record` to __LINKEDIT of the slice at `header_offset` and add an LC_NOTE pointing to it
+///
+/// A signed slice is refused, since the record would end up after the signature data and
+/// invalidate it, `sign` strips the signature before adding the record and signs again after. The
+/// record is skipped with a warning if __LINKEDIT is not at the end of the slice.
+pub fn add_provenance<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    record: &Provenance,
+    slice_size: &mut u64,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+    let commands = read_signature_commands(binary_file, header_offset)?;
+    if commands.signature.is_some() {
+        return Err(InsertDylibError::SignedProvenance);
+    }
+    let linkedit = match commands.linkedit {
+        Some(linkedit) if linkedit.fileoff + linkedit.filesize == *slice_size => linkedit,
+        _ => {
+            reporter.warning(
+                "__LINKEDIT segment is not at the end of the file, skipping the provenance record.",
+            );
+            return Ok(());
+        }
+    };
+
+    let cmdsize = NoteCommand::SIZE as u32;
+    let command_pos = header_offset + mach_header.len() + mach_header.sizeofcmds as u64;
+    // fails without touching the file if there is no room for the LC_NOTE
+    grow_load_command(
+        binary_file,
+        &mut mach_header,
+        endian,
+        header_offset,
+        command_pos,
+        0,
+        cmdsize,
+    )?;
+
+    let data = record.to_bytes();
+    let data_offset = round_up_u64(*slice_size, 8);
+    let data_end = round_up_u64(data_offset + data.len() as u64, 8);
+    binary_file.fbzero(header_offset + *slice_size, data_end - *slice_size)?;
+    binary_file.seek(SeekFrom::Start(header_offset + data_offset))?;
+    binary_file.write_all(&data)?;
+
+    resize_segment(
+        binary_file,
+        &linkedit,
+        endian,
+        data_end - linkedit.fileoff,
+        segment_page_size(mach_header.cputype),
+    )?;
+
+    let mut data_owner = [0u8; 16];
+    data_owner[..PROVENANCE_OWNER.len()].copy_from_slice(PROVENANCE_OWNER.as_bytes());
+    let note = NoteCommand {
+        cmd: LC_NOTE,
+        cmdsize,
+        data_owner,
+        offset: data_offset,
+        size: data.len() as u64,
+    };
+    binary_file.seek(SeekFrom::Start(command_pos))?;
+    binary_file.write_all(&note.to_bytes(endian))?;
+    mach_header.ncmds += 1;
+    write_mach_header(binary_file, header_offset, &mach_header, endian)?;
+
+    *slice_size = data_end;
+    Ok(())
+}
+
+/// Read the provenance records of the slice at `header_offset`, oldest first
+///
+/// Notes of other owners are skipped, and so are records that can't be parsed, with a warning.
+pub fn read_provenance<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    reporter: &mut Reporter,
+) -> Result<Vec<Provenance>> {
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut notes: Vec<NoteCommand> = Vec::new();
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for _ in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+        if lc.cmd == LC_NOTE {
+            let note: NoteCommand = binary_file.fpeek_struct(endian)?;
+            if get_segname(&note.data_owner) == PROVENANCE_OWNER {
+                notes.push(note);
+            }
+        }
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    let file_len = binary_file.flen()?;
+    let mut records = Vec::new();
+    for note in notes {
+        let end = header_offset
+            .checked_add(note.offset)
+            .and_then(|offset| offset.checked_add(note.size));
+        if end.is_none_or(|end| end > file_len) {
+            reporter.warning(format!(
+                "Provenance record at offset {:#x} runs past the end of the file.",
+                note.offset
+            ));
+            continue;
+        }
+        let mut data = vec![0u8; note.size as usize];
+        binary_file.seek(SeekFrom::Start(header_offset + note.offset))?;
+        binary_file.read_exact(&mut data)?;
+        match serde_json::from_slice(&data)
+            .ok()
+            .as_ref()
+            .and_then(Provenance::from_json)
+        {
+            Some(record) => records.push(record),
+            None => reporter.warning(format!(
+                "Cannot parse the provenance record at offset {:#x}.",
+                note.offset
+            )),
+        }
+    }
+
+    Ok(records)
+}
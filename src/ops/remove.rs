@@ -0,0 +1,85 @@
+use std::io::SeekFrom;
+
+use super::{read_dylib_name, read_mach_header, remove_load_command};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+use crate::utils::load_command_name;
+
+/// Remove every load command that loads `dylib_path`
+///
+/// Symbols bound to the removed dylib are left unresolved. Removing it also shifts the ordinals of
+/// the dylibs loaded after it, which is refused unless `all_yes` is set.
+pub fn remove_dylib<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    dylib_path: &str,
+    all_yes: bool,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    // position and size of every matching command, and the number of dylibs loaded before it
+    let mut matches: Vec<(u64, u32, usize)> = Vec::new();
+    let mut ndylibs = 0;
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        if let LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LOAD_UPWARD_DYLIB =
+            lc.cmd
+        {
+            match read_dylib_name(binary_file, lc.cmdsize, endian)? {
+                Ok(name) if name == dylib_path => {
+                    reporter.note(format!(
+                        "Removing {} {} (ordinal {})",
+                        load_command_name(lc.cmd),
+                        name,
+                        ndylibs + 1
+                    ));
+                    matches.push((binary_file.ftello()?, lc.cmdsize, ndylibs));
+                }
+                Ok(_) => (),
+                Err(e) => reporter.warning(format!(
+                    "Cannot get dylib path for load command at {}: {}",
+                    i, e
+                )),
+            }
+            ndylibs += 1;
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    let first_ordinal = match matches.first() {
+        Some((_, _, ordinal)) => *ordinal,
+        None => return Err(InsertDylibError::DylibNotFound(dylib_path.to_string())),
+    };
+
+    let shifted = ndylibs - first_ordinal - matches.len();
+    if shifted > 0 {
+        reporter.warning(format!(
+            "removing the dylib changes the ordinals of {} dylib(s) loaded after it, symbols bound to those ordinals will resolve to the wrong dylib.",
+            shifted
+        ));
+        if !all_yes {
+            reporter.note("Pass --all-yes to remove it anyway.");
+            return Err(InsertDylibError::OrdinalsShifted(shifted));
+        }
+    }
+
+    for (command_pos, cmdsize, _) in matches.iter().rev() {
+        remove_load_command(
+            binary_file,
+            &mut mach_header,
+            endian,
+            header_offset,
+            *command_pos,
+            *cmdsize,
+        )?;
+    }
+
+    Ok(())
+}
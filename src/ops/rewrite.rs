@@ -0,0 +1,364 @@
+use std::io::SeekFrom;
+use std::ops::Range;
+
+use super::id::rewrite_dylib_name;
+use super::{
+    grow_load_command, has_free_space, lc_str_command_size, read_dylib_name, read_lc_str,
+    read_mach_header,
+};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+use crate::utils::load_command_name;
+
+/// A rule mapping dylib and rpath paths to new ones
+#[derive(Debug, PartialEq)]
+pub enum RewriteRule {
+    /// Replace the leading `from` with `to`
+    Prefix { from: String, to: String },
+    /// Match the whole path against a glob `pattern`, where `*` matches any run of characters
+    /// and `?` a single one, and substitute `$1`, `$2`, ... in `replacement` with what the
+    /// wildcards matched
+    Glob {
+        pattern: String,
+        replacement: String,
+    },
+}
+
+impl RewriteRule {
+    /// Parse a rule given as `FROM=TO`
+    pub fn parse_prefix(rule: &str) -> Result<RewriteRule, String> {
+        let (from, to) = split_rule(rule)?;
+        Ok(RewriteRule::Prefix { from, to })
+    }
+
+    /// Parse a rule given as `PATTERN=REPLACEMENT`
+    pub fn parse_glob(rule: &str) -> Result<RewriteRule, String> {
+        let (pattern, replacement) = split_rule(rule)?;
+        Ok(RewriteRule::Glob {
+            pattern,
+            replacement,
+        })
+    }
+
+    /// Return the rewritten path if the rule matches `path`
+    pub fn apply(&self, path: &str) -> Option<String> {
+        match self {
+            RewriteRule::Prefix { from, to } => path
+                .strip_prefix(from.as_str())
+                .map(|rest| format!("{}{}", to, rest)),
+            RewriteRule::Glob {
+                pattern,
+                replacement,
+            } => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let path: Vec<char> = path.chars().collect();
+                let captures = glob_match(&pattern, &path)?;
+                Some(substitute_captures(replacement, &path, &captures))
+            }
+        }
+    }
+}
+
+fn split_rule(rule: &str) -> Result<(String, String), String> {
+    match rule.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err(format!("Invalid rewrite rule {}, expected FROM=TO", rule)),
+    }
+}
+
+/// Match the whole `path` against `pattern`, returning the span of `path` every wildcard matched,
+/// in pattern order
+///
+/// This is the usual iterative matcher that backtracks to the last `*` only, run back to front, so
+/// that later wildcards match as little and earlier `*` as much as possible, like a greedy `.*`.
+fn glob_match(pattern: &[char], path: &[char]) -> Option<Vec<Range<usize>>> {
+    let pattern: Vec<char> = pattern.iter().rev().copied().collect();
+    let path: Vec<char> = path.iter().rev().copied().collect();
+
+    let mut captures: Vec<Range<usize>> = Vec::new();
+    // position of the last `*` in the pattern and of its capture
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut s) = (0, 0);
+    while s < path.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, captures.len()));
+                captures.push(s..s);
+                p += 1;
+            }
+            Some('?') => {
+                captures.push(s..s + 1);
+                p += 1;
+                s += 1;
+            }
+            Some(c) if *c == path[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => {
+                // let the last `*` match one more character and retry from there
+                let (star_p, star_capture) = star?;
+                captures.truncate(star_capture + 1);
+                captures[star_capture].end += 1;
+                s = captures[star_capture].end;
+                p = star_p + 1;
+            }
+        }
+    }
+    // the rest of the pattern has to match the empty string
+    while pattern.get(p) == Some(&'*') {
+        captures.push(s..s);
+        p += 1;
+    }
+    if p != pattern.len() {
+        return None;
+    }
+
+    let len = path.len();
+    Some(
+        captures
+            .into_iter()
+            .rev()
+            .map(|capture| len - capture.end..len - capture.start)
+            .collect(),
+    )
+}
+
+fn substitute_captures(replacement: &str, path: &[char], captures: &[Range<usize>]) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '$' {
+            if let Some(index) = chars.peek().and_then(|next| next.to_digit(10)) {
+                chars.next();
+                if let Some(capture) = (index as usize)
+                    .checked_sub(1)
+                    .and_then(|index| captures.get(index))
+                {
+                    result.extend(&path[capture.clone()]);
+                }
+                continue;
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+struct PathChange {
+    command_pos: u64,
+    cmd: u32,
+    cmdsize: u32,
+    old_path: String,
+    new_path: String,
+}
+
+impl PathChange {
+    fn new_cmdsize(&self) -> u32 {
+        let fixed_len = match self.cmd {
+            LC_RPATH => RpathCommand::SIZE,
+            _ => DylibCommand::SIZE,
+        };
+        lc_str_command_size(fixed_len as u32, &self.new_path).max(self.cmdsize)
+    }
+}
+
+/// Rewrite the path of every dylib and rpath load command matching one of `rules`
+///
+/// Rules are tried in order and the first matching one wins. The old and new paths are
+/// printed before anything is written.
+pub fn rewrite_paths<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    rules: &[RewriteRule],
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mut mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let mut changes: Vec<PathChange> = Vec::new();
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        let path = match lc.cmd {
+            LC_ID_DYLIB | LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB
+            | LC_LOAD_UPWARD_DYLIB => Some(read_dylib_name(binary_file, lc.cmdsize, endian)?),
+            LC_RPATH => {
+                let rpath_command: RpathCommand = binary_file.fpeek_struct(endian)?;
+                Some(read_lc_str(
+                    binary_file,
+                    lc.cmdsize,
+                    rpath_command.path_offset,
+                )?)
+            }
+            _ => None,
+        };
+
+        match path {
+            Some(Ok(old_path)) => {
+                if let Some(new_path) = rules.iter().find_map(|rule| rule.apply(&old_path)) {
+                    if new_path != old_path {
+                        changes.push(PathChange {
+                            command_pos: binary_file.ftello()?,
+                            cmd: lc.cmd,
+                            cmdsize: lc.cmdsize,
+                            old_path,
+                            new_path,
+                        });
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                reporter.warning(format!("Cannot get path for load command at {}: {}", i, e));
+            }
+            None => (),
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    if changes.is_empty() {
+        reporter.note("No load commands matched the rewrite rules.");
+        return Ok(());
+    }
+
+    reporter.note(format!("Rewriting {} path(s):", changes.len()));
+    for change in &changes {
+        reporter.note(format!(
+            "    {}: {} -> {}",
+            load_command_name(change.cmd),
+            change.old_path,
+            change.new_path
+        ));
+    }
+
+    let growth: u32 = changes
+        .iter()
+        .map(|change| change.new_cmdsize() - change.cmdsize)
+        .sum();
+    binary_file.seek(SeekFrom::Start(
+        header_offset + mach_header.len() + mach_header.sizeofcmds as u64,
+    ))?;
+    if !has_free_space(binary_file, growth as u64)? {
+        return Err(InsertDylibError::NoSpace {
+            needed: growth as u64,
+        });
+    }
+
+    // rewrite from the last command backwards, so that growing a command doesn't move the ones still to do
+    for change in changes.iter().rev() {
+        binary_file.seek(SeekFrom::Start(change.command_pos))?;
+        match change.cmd {
+            LC_RPATH => {
+                rewrite_rpath(binary_file, &mut mach_header, endian, header_offset, change)?
+            }
+            _ => {
+                let dylib_command: DylibCommand = binary_file.fpeek_struct(endian)?;
+                rewrite_dylib_name(
+                    binary_file,
+                    &mut mach_header,
+                    endian,
+                    header_offset,
+                    change.command_pos,
+                    dylib_command,
+                    &change.new_path,
+                )?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rewrite_rpath<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    mach_header: &mut MachHeader,
+    endian: Endian,
+    header_offset: u64,
+    change: &PathChange,
+) -> Result<()> {
+    let new_cmdsize = change.new_cmdsize();
+    grow_load_command(
+        binary_file,
+        mach_header,
+        endian,
+        header_offset,
+        change.command_pos,
+        change.cmdsize,
+        new_cmdsize,
+    )?;
+
+    let rpath_command = RpathCommand {
+        cmd: change.cmd,
+        cmdsize: new_cmdsize,
+        path_offset: RpathCommand::SIZE as u32,
+    };
+
+    let mut command = rpath_command.to_bytes(endian);
+    command.extend_from_slice(change.new_path.as_bytes());
+    command.resize(new_cmdsize as usize, 0);
+
+    binary_file.seek(SeekFrom::Start(change.command_pos))?;
+    binary_file.write_all(&command)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(pattern: &str, path: &str) -> Option<Vec<String>> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let path: Vec<char> = path.chars().collect();
+        glob_match(&pattern, &path).map(|captures| {
+            captures
+                .into_iter()
+                .map(|capture| path[capture].iter().collect())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn glob_captures() {
+        assert_eq!(
+            captures(
+                "/opt/homebrew/*/lib/*.dylib",
+                "/opt/homebrew/Cellar/lib/libz.dylib"
+            ),
+            Some(vec!["Cellar".to_string(), "libz".to_string()])
+        );
+        // earlier `*` are greedy
+        assert_eq!(
+            captures("*/*.dylib", "/a/b/libx.dylib"),
+            Some(vec!["/a/b".to_string(), "libx".to_string()])
+        );
+        assert_eq!(
+            captures("lib?.*", "libz.1.dylib"),
+            Some(vec!["z".to_string(), "1.dylib".to_string()])
+        );
+        assert_eq!(captures("*", ""), Some(vec![String::new()]));
+        assert_eq!(captures("*.dylib", "libz.so"), None);
+        assert_eq!(captures("lib?", "lib"), None);
+    }
+
+    #[test]
+    fn glob_rule_substitutes_captures() {
+        let rule = RewriteRule::parse_glob("/opt/homebrew/*/lib/*=@rpath/$2 ($1)").unwrap();
+        assert_eq!(
+            rule.apply("/opt/homebrew/Cellar/lib/libz.dylib").as_deref(),
+            Some("@rpath/libz.dylib (Cellar)")
+        );
+        assert_eq!(rule.apply("/usr/lib/libz.dylib"), None);
+    }
+
+    #[test]
+    fn glob_match_is_not_exponential() {
+        let pattern = "*a".repeat(30) + "b";
+        assert_eq!(captures(&pattern, &"a".repeat(200)), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::io::SeekFrom;
+
+use super::{read_dylib_name, read_mach_header};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::report::Reporter;
+
+/// Turn every LC_LOAD_DYLIB for `dylib_path` into LC_LOAD_WEAK_DYLIB (`weak == true`) or back
+///
+/// Only the `cmd` field is rewritten, the rest of the load command is kept as is.
+pub fn set_dylib_weak<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    dylib_path: &str,
+    weak: bool,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let (mach_header, endian) = read_mach_header(binary_file, header_offset)?;
+
+    let new_cmd = match weak {
+        true => LC_LOAD_WEAK_DYLIB,
+        false => LC_LOAD_DYLIB,
+    };
+
+    let mut found = false;
+    binary_file.seek(SeekFrom::Start(header_offset + mach_header.len()))?;
+    for i in 0..mach_header.ncmds {
+        let lc: LoadCommand = binary_file.fpeek_struct(endian)?;
+
+        if lc.cmd == LC_LOAD_DYLIB || lc.cmd == LC_LOAD_WEAK_DYLIB {
+            let name = match read_dylib_name(binary_file, lc.cmdsize, endian)? {
+                Ok(name) => name,
+                Err(e) => {
+                    reporter.warning(format!(
+                        "Cannot get dylib path for load command at {}: {}",
+                        i, e
+                    ));
+                    binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+                    continue;
+                }
+            };
+
+            if name.eq(dylib_path) {
+                found = true;
+                if lc.cmd != new_cmd {
+                    binary_file.write_all(&endian.write_u32(new_cmd))?;
+                    binary_file.seek(SeekFrom::Current(-4))?;
+                } else {
+                    reporter.note("Load command for that dylib is already the requested kind.");
+                }
+            }
+        }
+
+        binary_file.seek(SeekFrom::Current(lc.cmdsize as i64))?;
+    }
+
+    match found {
+        true => Ok(()),
+        false => Err(InsertDylibError::DylibNotFound(dylib_path.to_string())),
+    }
+}
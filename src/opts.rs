@@ -1,24 +1,133 @@
+use crate::ops::rewrite::RewriteRule;
+use crate::report::Format;
+
+#[derive(Debug, PartialEq)]
+pub enum Operation {
+    /// Insert a new load command for the dylib
+    Insert,
+    /// Convert the existing load command for the dylib to LC_LOAD_WEAK_DYLIB (`true`)
+    /// or LC_LOAD_DYLIB (`false`)
+    SetWeak(bool),
+    /// Change the install name in LC_ID_DYLIB
+    SetId(String),
+    /// Rewrite dylib and rpath paths with the given rules
+    Rewrite(Vec<RewriteRule>),
+    /// Remove load commands for dylibs that are already loaded, optionally resolving `@rpath/`
+    Dedupe { resolve_rpath: bool },
+    /// Remove the load commands for the dylib
+    Remove,
+    /// Remove the code signature
+    StripSignature,
+    /// Replace the code signature with an ad-hoc signature for the given identifier
+    Sign { identifier: String },
+    /// List the dylibs loaded by the binary
+    List,
+    /// Summarize every slice of the binary
+    Info,
+    /// Print every load command of the binary
+    Dump,
+    /// Turn the original binary into the patched one with the patch file at this path
+    ApplyPatch(String),
+    /// Turn the patched binary back into the original one with the patch file at this path
+    RevertPatch(String),
+    /// Undo the writes recorded by `--write-log` in the log at this path
+    UndoWriteLog(String),
+    /// Print the provenance records of the binary
+    History,
+    /// Compare the load commands of the binary with those of the binary at this path
+    Diff(String),
+    /// Check the structure of the binary against the rules dyld enforces
+    Check,
+}
+
+impl Operation {
+    /// Name of the operation as reported in `--format json`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Insert => "insert",
+            Operation::SetWeak(true) => "make-weak",
+            Operation::SetWeak(false) => "make-strong",
+            Operation::SetId(_) => "set-id",
+            Operation::Rewrite(_) => "rewrite",
+            Operation::Dedupe { .. } => "dedupe",
+            Operation::Remove => "remove",
+            Operation::StripSignature => "strip",
+            Operation::Sign { .. } => "sign",
+            Operation::List => "list",
+            Operation::Info => "info",
+            Operation::Dump => "dump",
+            Operation::ApplyPatch(_) => "apply",
+            Operation::RevertPatch(_) => "revert",
+            Operation::UndoWriteLog(_) => "undo",
+            Operation::History => "history",
+            Operation::Diff(_) => "diff",
+            Operation::Check => "check",
+        }
+    }
+
+    /// Whether the operation writes a patched binary, as opposed to only inspecting it
+    pub fn modifies_binary(&self) -> bool {
+        !matches!(
+            self,
+            Operation::List
+                | Operation::Info
+                | Operation::Dump
+                | Operation::History
+                | Operation::Diff(_)
+                | Operation::Check
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct Opts {
+    pub operation: Operation,
     pub dylib_path: String,
+    pub dylib_file: Option<String>,
     pub binary_path: String,
     pub output_path: String,
     pub weak: bool,
     pub overwrite: bool,
+    pub inplace: bool,
+    pub backup_suffix: Option<String>,
+    pub preserve_mtime: bool,
+    /// Patch an in-memory copy and print what would change instead of writing the output
+    pub dry_run: bool,
+    /// Save every write to the output, with the bytes it replaced, to this path
+    pub write_log: Option<String>,
+    /// Save a patch file from the input to the output to this path
+    pub export_patch: Option<String>,
+    /// Record the tool, the operation and the original slice hashes in an LC_NOTE
+    pub provenance: bool,
+    /// Check the structure of the output before writing it
+    pub verify_output: bool,
     pub strip_codesign: bool,
     pub all_yes: bool,
+    pub format: Format,
 }
 
 impl Opts {
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Opts {
         Opts {
+            operation: Operation::Insert,
             dylib_path: "".to_string(),
+            dylib_file: None,
             binary_path: "".to_string(),
             output_path: "".to_string(),
             weak: false,
             overwrite: false,
+            inplace: false,
+            backup_suffix: None,
+            preserve_mtime: false,
+            dry_run: false,
+            write_log: None,
+            export_patch: None,
+            provenance: false,
+            verify_output: false,
             strip_codesign: false,
             all_yes: false,
+            format: Format::Text,
         }
     }
 }
@@ -0,0 +1,228 @@
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::report::Reporter;
+
+/// A copy of the input binary that is patched in a temporary file next to the output path and
+/// only renamed over the output once it is complete
+///
+/// If it is dropped without `commit`, the temporary file is removed and the output path is left
+/// untouched.
+pub struct AtomicOutput {
+    file: File,
+    source_path: PathBuf,
+    temp_path: PathBuf,
+    output_path: PathBuf,
+    committed: bool,
+}
+
+impl AtomicOutput {
+    /// Copy `source_path`, including its permissions, to a new temporary file in the directory of
+    /// `output_path`
+    ///
+    /// On Linux the copy is a reflink where the filesystem supports it, which makes it nearly free
+    /// even for large binaries. Elsewhere the binary is copied in full.
+    pub fn copy_from(source_path: &Path, output_path: &Path) -> io::Result<AtomicOutput> {
+        let file_name = output_path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid output path {}", output_path.display()),
+            )
+        })?;
+        let temp_path = create_temp_file(output_dir(output_path), file_name)?;
+
+        let file = copy_file(source_path, &temp_path)
+            .and_then(|_| OpenOptions::new().read(true).write(true).open(&temp_path));
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+
+        Ok(AtomicOutput {
+            file,
+            source_path: source_path.to_path_buf(),
+            temp_path,
+            output_path: output_path.to_path_buf(),
+            committed: false,
+        })
+    }
+
+    /// The temporary file to patch
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Flush the patched binary to disk and rename it over the output path
+    ///
+    /// Extended attributes of the source are copied over, attributes that can't be copied are
+    /// warned about, and so is its modification time if `preserve_mtime` is set.
+    pub fn commit(mut self, preserve_mtime: bool, reporter: &mut Reporter) -> io::Result<()> {
+        copy_xattrs(&self.source_path, &self.temp_path, reporter);
+        if preserve_mtime {
+            let modified = fs::metadata(&self.source_path)?.modified()?;
+            self.file.set_modified(modified)?;
+        }
+        self.file.sync_all()?;
+
+        fs::rename(&self.temp_path, &self.output_path)?;
+        self.committed = true;
+
+        // make the rename itself durable
+        #[cfg(unix)]
+        File::open(output_dir(&self.output_path))?.sync_all()?;
+
+        Ok(())
+    }
+}
+
+impl Drop for AtomicOutput {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Take an exclusive advisory lock on `path`, blocking until other processes release it
+///
+/// The lock lives as long as the returned file. Since a patched binary is renamed over `path`,
+/// a process that was waiting may end up holding the lock on the old file, so the path is opened
+/// again until the locked file is the one it refers to.
+pub fn lock_path(path: &Path) -> io::Result<File> {
+    loop {
+        let file = File::open(path)?;
+        file.lock()?;
+        if same_file(&file.metadata()?, &fs::metadata(path)?) {
+            return Ok(file);
+        }
+    }
+}
+
+/// Keep the binary at `path` as `<path><suffix>`, replacing an older backup
+///
+/// The binary is hard linked when possible, so the backup is the original file itself once the
+/// patched binary is renamed over `path`.
+pub fn backup(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let mut backup_path = path.as_os_str().to_os_string();
+    backup_path.push(suffix);
+    let backup_path = PathBuf::from(backup_path);
+
+    match fs::remove_file(&backup_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    if fs::hard_link(path, &backup_path).is_err() {
+        fs::copy(path, &backup_path)?;
+    }
+
+    Ok(backup_path)
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    true
+}
+
+fn output_dir(output_path: &Path) -> &Path {
+    match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Copy `source_path` and its permissions over the file at `dest_path`
+fn copy_file(source_path: &Path, dest_path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    if reflink(source_path, dest_path)? {
+        return Ok(());
+    }
+
+    fs::copy(source_path, dest_path)?;
+    Ok(())
+}
+
+/// Make the empty file at `dest_path` share the blocks of `source_path` (FICLONE), so that only
+/// the blocks modified later are actually copied
+///
+/// Returns `false` if the filesystem doesn't support reflinks between these files.
+#[cfg(target_os = "linux")]
+fn reflink(source_path: &Path, dest_path: &Path) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = File::open(source_path)?;
+    let dest = OpenOptions::new().write(true).open(dest_path)?;
+    let ret = unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if ret < 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            // unsupported filesystem, different filesystems or not a regular file
+            Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EXDEV | libc::EINVAL | libc::EBADF) => {
+                Ok(false)
+            }
+            _ => Err(e),
+        };
+    }
+
+    dest.set_permissions(source.metadata()?.permissions())?;
+    Ok(true)
+}
+
+/// Create a new, empty `.<file_name>.<pid>.<n>.tmp` in `dir`
+fn create_temp_file(dir: &Path, file_name: &OsStr) -> io::Result<PathBuf> {
+    let mut attempt = 0;
+    loop {
+        let mut temp_name = OsStr::new(".").to_os_string();
+        temp_name.push(file_name);
+        temp_name.push(format!(".{}.{}.tmp", process::id(), attempt));
+        let temp_path = dir.join(temp_name);
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(_) => return Ok(temp_path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn copy_xattrs(source_path: &Path, dest_path: &Path, reporter: &mut Reporter) {
+    let names = match xattr::list(source_path) {
+        Ok(names) => names,
+        Err(e) => {
+            reporter.warning(format!("couldn't read extended attributes: {}", e));
+            return;
+        }
+    };
+    for name in names {
+        let copied = xattr::get(source_path, &name).and_then(|value| match value {
+            Some(value) => xattr::set(dest_path, &name, &value),
+            None => Ok(()),
+        });
+        if let Err(e) = copied {
+            reporter.warning(format!(
+                "couldn't copy extended attribute {}: {}",
+                name.to_string_lossy(),
+                e
+            ));
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_xattrs(_source_path: &Path, _dest_path: &Path, _reporter: &mut Reporter) {}
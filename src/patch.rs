@@ -0,0 +1,392 @@
+use std::cmp::{max, min};
+use std::io::{Cursor, SeekFrom};
+
+use crate::arch::{arch_name, read_slice_arch, SliceArch};
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::macho::endian::*;
+use crate::macho::macho::*;
+use crate::macho::prelude::*;
+use crate::ops::bounds::{check_slice, read_fat_archs};
+use crate::ops::codesign::{sign_adhoc, signature_reserve, strip_code_signature, StripOutcome};
+use crate::ops::dedupe::dedupe_dylibs;
+use crate::ops::id::set_install_name;
+use crate::ops::insert::insert_dylib;
+use crate::ops::provenance::{add_provenance, hash_slice, provenance_reserve, Provenance};
+use crate::ops::read_mach_header;
+use crate::ops::remove::remove_dylib;
+use crate::ops::rewrite::rewrite_paths;
+use crate::ops::weak::set_dylib_weak;
+use crate::opts::{Operation, Opts};
+use crate::report::{HeaderCounts, Reporter, SliceReport};
+use crate::utils::round_up_u64;
+
+/// Outcome of a successful `patch_binary`
+#[derive(Debug)]
+pub struct Patched {
+    /// New length of the binary
+    pub filesize: u64,
+    /// Whether the binary is a fat binary
+    pub fat: bool,
+}
+
+/// Apply the operation in `options` to a thin or fat Mach-O binary
+///
+/// `dylib_archs` are the slices of the dylib to insert, used to check that it can be loaded, and
+/// may be empty to skip the check. The storage is truncated if the binary shrank, e.g. after
+/// stripping the code signature. On error the storage may be partially patched.
+pub fn patch_binary<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    reporter: &mut Reporter,
+) -> Result<Patched> {
+    let patched = patch_slices(binary_file, options, dylib_archs, reporter)?;
+    binary_file.fset_len(patched.filesize)?;
+    Ok(patched)
+}
+
+/// Apply the operation in `options` to a binary already in memory
+///
+/// `binary` is only modified if the operation succeeded in every slice.
+pub fn patch_buffer(
+    binary: &mut Vec<u8>,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    reporter: &mut Reporter,
+) -> Result<Patched> {
+    let mut patched_binary = Cursor::new(binary.clone());
+    let patched = patch_binary(&mut patched_binary, options, dylib_archs, reporter)?;
+    *binary = patched_binary.into_inner();
+    Ok(patched)
+}
+
+fn patch_slices<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    reporter: &mut Reporter,
+) -> Result<Patched> {
+    let mut filesize = binary_file.flen()?;
+
+    binary_file.seek(SeekFrom::Start(0))?;
+    let mut magic_buffer = [0; 4];
+    binary_file.read_exact(&mut magic_buffer)?;
+
+    let magic = u32::from_be_bytes(magic_buffer);
+
+    match magic {
+        MH_CIGAM_64 | MH_MAGIC_64 | MH_CIGAM | MH_MAGIC => {
+            apply_operation(
+                binary_file,
+                0,
+                options,
+                dylib_archs,
+                &mut filesize,
+                reporter,
+            )?;
+            Ok(Patched {
+                filesize,
+                fat: false,
+            })
+        }
+        FAT_MAGIC | FAT_CIGAM => {
+            let fat_endian = match magic {
+                FAT_MAGIC => Endian::Big,
+                _ => Endian::Little,
+            };
+            let filesize = patch_fat(binary_file, fat_endian, options, dylib_archs, reporter)?;
+            Ok(Patched {
+                filesize,
+                fat: true,
+            })
+        }
+        _ => Err(InsertDylibError::BadMagic(magic)),
+    }
+}
+
+/// Apply the operation to every slice of a fat binary
+///
+/// Slices are processed one after another and moved down if an earlier slice shrank. Every slice
+/// is attempted so that the report covers all of them, but the fat header is only rewritten if
+/// all of them succeeded, otherwise the first error is returned. Returns the new file size.
+fn patch_fat<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    fat_endian: Endian,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    reporter: &mut Reporter,
+) -> Result<u64> {
+    let (fh, mut archs) = read_fat_archs(binary_file, fat_endian)?;
+
+    let nfat_arch = archs.len();
+    reporter.note(format!("Binary is a fat binary with {} archs.", nfat_arch));
+
+    let provenance = match options.provenance {
+        true => provenance_reserve(options),
+        false => 0,
+    };
+    if let Operation::Sign { ref identifier } = options.operation {
+        make_room(binary_file, &mut archs, |slice_size| {
+            provenance + signature_reserve(slice_size + provenance, identifier)
+        })?;
+    } else if provenance > 0 {
+        make_room(binary_file, &mut archs, |_| provenance)?;
+    }
+
+    let mut filesize = binary_file.flen()?;
+    let mut offset: u64 = 0;
+    if nfat_arch > 0 {
+        offset = archs[0].offset as u64;
+    }
+
+    let mut results: Vec<Result<()>> = Vec::new();
+    for (arch_index, current_arch) in archs.iter_mut().enumerate() {
+        let orig_offset = current_arch.offset as u64;
+        let orig_slice_size = current_arch.size as u64;
+
+        let align = 1u64 << current_arch.align;
+        offset = round_up_u64(offset, align);
+
+        if orig_offset != offset {
+            binary_file.fmemmove(offset, orig_offset, orig_slice_size)?;
+            let diff = (offset as i64 - orig_offset as i64).unsigned_abs();
+            binary_file.fbzero(min(offset, orig_offset) + orig_slice_size, diff)?;
+            current_arch.offset = offset as u32;
+        }
+
+        let mut slice_size = orig_slice_size;
+        let result = apply_operation(
+            binary_file,
+            offset,
+            options,
+            dylib_archs,
+            &mut slice_size,
+            reporter,
+        );
+        results.push(result);
+
+        if slice_size < orig_slice_size && arch_index < nfat_arch - 1 {
+            binary_file.fbzero(offset + slice_size, orig_slice_size - slice_size)?;
+        }
+
+        filesize = offset + slice_size;
+        offset += slice_size;
+        current_arch.size = slice_size as u32;
+    }
+
+    reporter.note("Summary:");
+    for (arch_index, (current_arch, result)) in archs.iter().zip(&results).enumerate() {
+        let status = match result {
+            Ok(()) => "ok".to_string(),
+            Err(e) => format!("failed ({})", e),
+        };
+        reporter.note(format!(
+            "    arch #{} ({}): {}",
+            arch_index + 1,
+            arch_name(current_arch.cputype, current_arch.cpusubtype),
+            status
+        ));
+    }
+    if let Some(e) = results.into_iter().find_map(Result::err) {
+        return Err(e);
+    }
+
+    binary_file.seek(SeekFrom::Start(0))?;
+    binary_file.write_all(&fh.to_bytes(fat_endian))?;
+    for current_arch in &archs {
+        binary_file.write_all(&current_arch.to_bytes(fat_endian))?;
+    }
+
+    Ok(filesize)
+}
+
+/// Move the slices of a fat binary apart so that every slice can grow by `reserve(slice size)`
+/// bytes without running into the next one
+///
+/// Slices only move towards the end of the file, the space freed or reserved is zeroed.
+fn make_room<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    archs: &mut [FatArch],
+    reserve: impl Fn(u64) -> u64,
+) -> Result<()> {
+    let mut new_offsets: Vec<u64> = Vec::new();
+    let mut offset = 0u64;
+    for current_arch in archs.iter() {
+        let align = 1u64 << current_arch.align;
+        let new_offset = round_up_u64(max(offset, current_arch.offset as u64), align);
+        if new_offset > u32::MAX as u64 {
+            return Err(InsertDylibError::NoSpace {
+                needed: new_offset - current_arch.offset as u64,
+            });
+        }
+        new_offsets.push(new_offset);
+        offset = new_offset + current_arch.size as u64 + reserve(current_arch.size as u64);
+    }
+
+    // move the last slice first, so that no slice is overwritten before it was moved
+    let mut next_offset = offset;
+    for (current_arch, new_offset) in archs.iter_mut().zip(new_offsets).rev() {
+        let orig_offset = current_arch.offset as u64;
+        let slice_size = current_arch.size as u64;
+        if new_offset != orig_offset {
+            binary_file.fmemmove(new_offset, orig_offset, slice_size)?;
+            binary_file.fbzero(orig_offset, min(new_offset - orig_offset, slice_size))?;
+            current_arch.offset = new_offset as u32;
+        }
+        binary_file.fbzero(
+            new_offset + slice_size,
+            next_offset - new_offset - slice_size,
+        )?;
+        next_offset = new_offset;
+    }
+
+    Ok(())
+}
+
+/// Apply the operation in `options` to the slice at `header_offset`, updating `slice_size` if
+/// the slice shrank or grew
+///
+/// The load command counts before and after are reported with `Reporter::slice`, along with the
+/// warnings the operation reported for the slice.
+fn apply_operation<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    slice_size: &mut u64,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    check_slice(binary_file, header_offset, *slice_size)?;
+    let arch = read_slice_arch(binary_file, header_offset)?;
+    let (before, _) = read_mach_header(binary_file, header_offset)?;
+    let warning_count = reporter.warning_count();
+    let provenance = match options.provenance {
+        true => Some(Provenance::new(
+            options,
+            hash_slice(binary_file, header_offset, *slice_size)?,
+        )),
+        false => None,
+    };
+
+    let result = run_operation(
+        binary_file,
+        header_offset,
+        options,
+        dylib_archs,
+        provenance.as_ref(),
+        slice_size,
+        reporter,
+    )
+    // the load commands may have grown into the next slice
+    .and_then(|()| check_slice(binary_file, header_offset, *slice_size));
+    let after = match result {
+        Ok(()) => Some(read_mach_header(binary_file, header_offset)?.0),
+        Err(_) => None,
+    };
+
+    let warnings = reporter.take_warnings(warning_count);
+    reporter.slice(SliceReport {
+        arch: arch.name(),
+        offset: header_offset,
+        before: HeaderCounts {
+            ncmds: before.ncmds,
+            sizeofcmds: before.sizeofcmds,
+        },
+        after: after.map(|after| HeaderCounts {
+            ncmds: after.ncmds,
+            sizeofcmds: after.sizeofcmds,
+        }),
+        error: result.as_ref().err().map(ToString::to_string),
+        warnings,
+    });
+    result
+}
+
+/// Run the operation on a slice, then add the provenance record if there is one
+fn run_operation<F: InsertDylibFileExt>(
+    binary_file: &mut F,
+    header_offset: u64,
+    options: &Opts,
+    dylib_archs: &[SliceArch],
+    provenance: Option<&Provenance>,
+    slice_size: &mut u64,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    match options.operation {
+        Operation::Insert => insert_dylib(
+            binary_file,
+            header_offset,
+            options,
+            dylib_archs,
+            slice_size,
+            reporter,
+        ),
+        Operation::SetWeak(weak) => set_dylib_weak(
+            binary_file,
+            header_offset,
+            &options.dylib_path,
+            weak,
+            reporter,
+        ),
+        Operation::SetId(ref install_name) => {
+            set_install_name(binary_file, header_offset, install_name, reporter)
+        }
+        Operation::Rewrite(ref rules) => rewrite_paths(binary_file, header_offset, rules, reporter),
+        Operation::Dedupe { resolve_rpath } => dedupe_dylibs(
+            binary_file,
+            header_offset,
+            resolve_rpath,
+            options.all_yes,
+            reporter,
+        ),
+        Operation::Remove => remove_dylib(
+            binary_file,
+            header_offset,
+            &options.dylib_path,
+            options.all_yes,
+            reporter,
+        ),
+        // a signature that can't be stripped fails the command even with --all-yes, since
+        // stripping is all it does
+        Operation::StripSignature => {
+            match strip_code_signature(binary_file, header_offset, false, slice_size, reporter)? {
+                StripOutcome::Stripped => Ok(()),
+                StripOutcome::NotSigned => {
+                    reporter.note("Binary is not signed.");
+                    Ok(())
+                }
+                StripOutcome::Skipped => Err(InsertDylibError::SignatureNotLast),
+            }
+        }
+        Operation::Sign { ref identifier } => {
+            // the new signature has to cover the record, so it goes in first
+            if let Some(provenance) = provenance {
+                if strip_code_signature(binary_file, header_offset, false, slice_size, reporter)?
+                    == StripOutcome::Stripped
+                {
+                    reporter.note("Replacing the existing code signature.");
+                }
+                add_provenance(binary_file, header_offset, provenance, slice_size, reporter)?;
+            }
+            return sign_adhoc(binary_file, header_offset, identifier, slice_size, reporter);
+        }
+        // nothing to patch, or not per slice
+        Operation::List
+        | Operation::Info
+        | Operation::Dump
+        | Operation::History
+        | Operation::Diff(_)
+        | Operation::Check
+        | Operation::ApplyPatch(_)
+        | Operation::RevertPatch(_)
+        | Operation::UndoWriteLog(_) => return Ok(()),
+    }?;
+
+    match provenance {
+        Some(provenance) => {
+            add_provenance(binary_file, header_offset, provenance, slice_size, reporter)
+        }
+        None => Ok(()),
+    }
+}
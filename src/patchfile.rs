@@ -0,0 +1,379 @@
+//! Compact patch files describing how a patched binary differs from its input
+//!
+//! All integers are little endian:
+//!
+//! - `PATCH_MAGIC` and the format version as a `u32`
+//! - the original and patched lengths as `u64`
+//! - the SHA-256 of the original and of the patched binary
+//! - the number of ranges as a `u64`, then for every range its offset and length as `u64`, the
+//!   original bytes and the patched bytes, both cut off where their binary ends
+//!
+//! Since both the original and the patched bytes are kept, a patch can be applied to the original
+//! binary as well as reverted from the patched one.
+
+use std::cmp::min;
+use std::io::SeekFrom;
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{InsertDylibError, Result};
+use crate::storage::Storage;
+
+pub const PATCH_MAGIC: &[u8; 8] = b"IDYLPTCH";
+pub const PATCH_VERSION: u32 = 1;
+
+/// Ranges closer than this are merged, as every range costs 16 bytes and every byte in between 2
+const MERGE_GAP: usize = 8;
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Bytes that differ between the original and the patched binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchRange {
+    pub offset: u64,
+    /// Shorter than `new` if the range runs past the end of the original binary
+    pub old: Vec<u8>,
+    /// Shorter than `old` if the range runs past the end of the patched binary
+    pub new: Vec<u8>,
+}
+
+impl PatchRange {
+    pub fn len(&self) -> u64 {
+        self.old.len().max(self.new.len()) as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchFile {
+    pub original_len: u64,
+    pub patched_len: u64,
+    pub original_hash: [u8; 32],
+    pub patched_hash: [u8; 32],
+    pub ranges: Vec<PatchRange>,
+}
+
+/// Read the chunk at `offset`, shorter than `CHUNK_SIZE` only at the end of the storage
+fn read_chunk<S: Storage>(storage: &mut S, offset: u64, len: u64, buf: &mut Vec<u8>) -> Result<()> {
+    buf.resize(
+        min(len.saturating_sub(offset), CHUNK_SIZE as u64) as usize,
+        0,
+    );
+    storage.seek(SeekFrom::Start(offset))?;
+    storage.read_exact(buf)?;
+    Ok(())
+}
+
+/// SHA-256 of the whole storage
+fn hash_storage<S: Storage>(storage: &mut S) -> Result<[u8; 32]> {
+    let len = storage.flen()?;
+    let mut hasher = Sha256::new();
+    let mut buf = Vec::new();
+    let mut offset = 0u64;
+    while offset < len {
+        read_chunk(storage, offset, len, &mut buf)?;
+        hasher.update(&buf);
+        offset += buf.len() as u64;
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Collects the differing ranges while the binaries are compared byte by byte
+#[derive(Default)]
+struct RangeBuilder {
+    ranges: Vec<PatchRange>,
+    current: Option<PatchRange>,
+    /// Equal bytes seen since the last difference in `current`
+    pending: Vec<u8>,
+}
+
+impl RangeBuilder {
+    fn push(&mut self, offset: u64, old: Option<u8>, new: Option<u8>) {
+        if old == new {
+            if self.current.is_some() {
+                self.pending.push(old.unwrap_or_default());
+                if self.pending.len() >= MERGE_GAP {
+                    self.finish();
+                }
+            }
+            return;
+        }
+
+        let range = self.current.get_or_insert_with(|| PatchRange {
+            offset,
+            old: Vec::new(),
+            new: Vec::new(),
+        });
+        range.old.extend_from_slice(&self.pending);
+        range.new.extend_from_slice(&self.pending);
+        self.pending.clear();
+        range.old.extend(old);
+        range.new.extend(new);
+    }
+
+    fn finish(&mut self) {
+        self.ranges.extend(self.current.take());
+        self.pending.clear();
+    }
+}
+
+impl PatchFile {
+    /// Compare the original binary with the patched one
+    pub fn between<A: Storage, B: Storage>(original: &mut A, patched: &mut B) -> Result<PatchFile> {
+        let original_len = original.flen()?;
+        let patched_len = patched.flen()?;
+        let mut original_hasher = Sha256::new();
+        let mut patched_hasher = Sha256::new();
+        let mut builder = RangeBuilder::default();
+
+        let mut old_chunk = Vec::new();
+        let mut new_chunk = Vec::new();
+        let mut offset = 0u64;
+        while offset < original_len.max(patched_len) {
+            read_chunk(original, offset, original_len, &mut old_chunk)?;
+            read_chunk(patched, offset, patched_len, &mut new_chunk)?;
+            original_hasher.update(&old_chunk);
+            patched_hasher.update(&new_chunk);
+
+            let chunk_len = old_chunk.len().max(new_chunk.len());
+            if old_chunk != new_chunk || builder.current.is_some() {
+                for i in 0..chunk_len {
+                    builder.push(
+                        offset + i as u64,
+                        old_chunk.get(i).copied(),
+                        new_chunk.get(i).copied(),
+                    );
+                }
+            }
+            offset += chunk_len as u64;
+        }
+        builder.finish();
+
+        Ok(PatchFile {
+            original_len,
+            patched_len,
+            original_hash: original_hasher.finalize().into(),
+            patched_hash: patched_hasher.finalize().into(),
+            ranges: builder.ranges,
+        })
+    }
+
+    /// Turn the original binary in `storage` into the patched one
+    pub fn apply<S: Storage>(&self, storage: &mut S) -> Result<()> {
+        self.check(storage, self.original_len, &self.original_hash, "original")?;
+        for range in &self.ranges {
+            storage.seek(SeekFrom::Start(range.offset))?;
+            storage.write_all(&range.new)?;
+        }
+        storage.fset_len(self.patched_len)?;
+        Ok(())
+    }
+
+    /// Turn the patched binary in `storage` back into the original one
+    pub fn revert<S: Storage>(&self, storage: &mut S) -> Result<()> {
+        self.check(storage, self.patched_len, &self.patched_hash, "patched")?;
+        for range in &self.ranges {
+            storage.seek(SeekFrom::Start(range.offset))?;
+            storage.write_all(&range.old)?;
+        }
+        storage.fset_len(self.original_len)?;
+        Ok(())
+    }
+
+    fn check<S: Storage>(
+        &self,
+        storage: &mut S,
+        len: u64,
+        hash: &[u8; 32],
+        which: &str,
+    ) -> Result<()> {
+        if storage.flen()? != len || hash_storage(storage)? != *hash {
+            return Err(InsertDylibError::BadPatch(format!(
+                "the binary is not the {} binary of the patch",
+                which
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(PATCH_MAGIC);
+        bytes.extend_from_slice(&PATCH_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.original_len.to_le_bytes());
+        bytes.extend_from_slice(&self.patched_len.to_le_bytes());
+        bytes.extend_from_slice(&self.original_hash);
+        bytes.extend_from_slice(&self.patched_hash);
+        bytes.extend_from_slice(&(self.ranges.len() as u64).to_le_bytes());
+        for range in &self.ranges {
+            bytes.extend_from_slice(&range.offset.to_le_bytes());
+            bytes.extend_from_slice(&range.len().to_le_bytes());
+            bytes.extend_from_slice(&range.old);
+            bytes.extend_from_slice(&range.new);
+        }
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<PatchFile> {
+        let mut reader = PatchReader { bytes };
+        if reader.take(PATCH_MAGIC.len())? != PATCH_MAGIC {
+            return Err(InsertDylibError::BadPatch("not a patch file".to_string()));
+        }
+        let version = u32::from_le_bytes(reader.array()?);
+        if version != PATCH_VERSION {
+            return Err(InsertDylibError::BadPatch(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+
+        let original_len = reader.u64()?;
+        let patched_len = reader.u64()?;
+        let original_hash = reader.array()?;
+        let patched_hash = reader.array()?;
+        let nranges = reader.u64()?;
+
+        let mut ranges = Vec::new();
+        for _ in 0..nranges {
+            let offset = reader.u64()?;
+            let len = reader.u64()?;
+            let old_len = min(len, original_len.saturating_sub(offset));
+            let new_len = min(len, patched_len.saturating_sub(offset));
+            if old_len.max(new_len) != len {
+                return Err(InsertDylibError::BadPatch(format!(
+                    "range at {:#x} runs past the end of both binaries",
+                    offset
+                )));
+            }
+            ranges.push(PatchRange {
+                offset,
+                old: reader.take(old_len as usize)?.to_vec(),
+                new: reader.take(new_len as usize)?.to_vec(),
+            });
+        }
+
+        Ok(PatchFile {
+            original_len,
+            patched_len,
+            original_hash,
+            patched_hash,
+            ranges,
+        })
+    }
+}
+
+struct PatchReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PatchReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            return Err(InsertDylibError::BadPatch(
+                "patch file is truncated".to_string(),
+            ));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Check `between`, `to_bytes`, `parse`, `apply` and `revert` on a pair of buffers
+    fn roundtrip(original: Vec<u8>, patched: Vec<u8>) -> PatchFile {
+        let patch = PatchFile::between(
+            &mut Cursor::new(original.clone()),
+            &mut Cursor::new(patched.clone()),
+        )
+        .unwrap();
+        let parsed = PatchFile::parse(&patch.to_bytes()).unwrap();
+        assert_eq!(parsed, patch);
+
+        let mut binary = Cursor::new(original.clone());
+        parsed.apply(&mut binary).unwrap();
+        assert!(binary.get_ref() == &patched);
+        parsed.revert(&mut binary).unwrap();
+        assert!(binary.into_inner() == original);
+        patch
+    }
+
+    #[test]
+    fn roundtrip_grown_binary() {
+        let original: Vec<u8> = (0..200).collect();
+        let mut patched = original.clone();
+        patched[10] = 0xff;
+        // close enough to the first change to be merged into one range
+        patched[14] = 0xff;
+        patched[100..104].copy_from_slice(&[0xee; 4]);
+        patched.extend_from_slice(&[0xdd; 20]);
+
+        let patch = roundtrip(original, patched);
+        assert_eq!(patch.ranges.len(), 3);
+        assert_eq!(patch.ranges[0].offset, 10);
+        assert_eq!(patch.ranges[0].len(), 5);
+        assert_eq!(patch.ranges[2].old, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn roundtrip_shrunk_binary() {
+        let original: Vec<u8> = (0..200).collect();
+        let mut patched = original[..150].to_vec();
+        patched[0] = 0xff;
+        roundtrip(original, patched);
+    }
+
+    #[test]
+    fn roundtrip_more_than_a_chunk() {
+        let original: Vec<u8> = (0..CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let mut patched = original.clone();
+        // straddles the chunk boundary
+        patched[CHUNK_SIZE - 2..CHUNK_SIZE + 2].copy_from_slice(&[0; 4]);
+        assert_eq!(roundtrip(original, patched).ranges.len(), 1);
+    }
+
+    #[test]
+    fn apply_rejects_other_binaries() {
+        let original: Vec<u8> = (0..64).collect();
+        let mut patched = original.clone();
+        patched[0] = 0xff;
+        let patch =
+            PatchFile::between(&mut Cursor::new(original), &mut Cursor::new(patched)).unwrap();
+
+        let mut other = Cursor::new(vec![0u8; 64]);
+        assert!(matches!(
+            patch.apply(&mut other),
+            Err(InsertDylibError::BadPatch(_))
+        ));
+        assert!(matches!(
+            patch.revert(&mut other),
+            Err(InsertDylibError::BadPatch(_))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_truncated_patch() {
+        let original: Vec<u8> = (0..64).collect();
+        let patched = vec![0u8; 64];
+        let bytes = PatchFile::between(&mut Cursor::new(original), &mut Cursor::new(patched))
+            .unwrap()
+            .to_bytes();
+        assert!(PatchFile::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PatchFile::parse(b"not a patch").is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use serde_json::{json, Value};
+
+use crate::error::{InsertDylibError, Result};
+use crate::extensions::InsertDylibFileExt;
+use crate::storage::Storage;
+
+pub const WRITE_LOG_VERSION: u32 = 1;
+
+/// A single change made to the storage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// `new` was written at `offset`, replacing `old`
+    ///
+    /// `old` is shorter than `new` if the write went past the end of the storage.
+    Write {
+        offset: u64,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    /// The storage was resized from `old_len` to `new_len`, `removed` are the bytes cut off if it
+    /// shrank
+    SetLen {
+        old_len: u64,
+        new_len: u64,
+        removed: Vec<u8>,
+    },
+    /// `length` bytes at `offset` were moved to `destination`, like a whole slice of a fat binary
+    ///
+    /// Only the destination bytes outside of the source range are kept in `overwritten`, the rest
+    /// is recovered by moving the bytes back.
+    Move {
+        offset: u64,
+        destination: u64,
+        length: u64,
+        overwritten: Vec<u8>,
+    },
+}
+
+/// Every change made through a `Recorder`, in order
+#[derive(Debug, Clone, Default)]
+pub struct WriteLog {
+    /// Length of the storage before the first change
+    pub original_len: u64,
+    pub changes: Vec<Change>,
+}
+
+/// A `Storage` that records every byte written to the one it wraps, along with the bytes it
+/// replaced
+///
+/// Copies through `fcopy_range` are recorded as a single `Change::Move`, so that moving a slice
+/// doesn't keep every byte of it twice.
+pub struct Recorder<S: Storage> {
+    inner: S,
+    log: WriteLog,
+}
+
+impl<S: Storage> Recorder<S> {
+    pub fn new(mut inner: S) -> io::Result<Recorder<S>> {
+        let original_len = inner.flen()?;
+        Ok(Recorder {
+            inner,
+            log: WriteLog {
+                original_len,
+                changes: Vec::new(),
+            },
+        })
+    }
+
+    pub fn log(&self) -> &WriteLog {
+        &self.log
+    }
+
+    pub fn into_parts(self) -> (S, WriteLog) {
+        (self.inner, self.log)
+    }
+
+    /// Read up to `len` bytes at `offset`, fewer if the storage ends before
+    fn read_existing(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let storage_len = self.inner.flen()?;
+        let len = len.min(storage_len.saturating_sub(offset));
+        let mut buf = vec![0u8; len as usize];
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Append a write to the log, merged into the previous one if it continues it
+    fn record_write(&mut self, offset: u64, old: Vec<u8>, new: &[u8]) {
+        if let Some(Change::Write {
+            offset: last_offset,
+            old: last_old,
+            new: last_new,
+        }) = self.log.changes.last_mut()
+        {
+            // only merge while the old bytes stay contiguous too
+            if *last_offset + last_new.len() as u64 == offset && last_old.len() == last_new.len() {
+                last_old.extend(old);
+                last_new.extend_from_slice(new);
+                return;
+            }
+        }
+        self.log.changes.push(Change::Write {
+            offset,
+            old,
+            new: new.to_vec(),
+        });
+    }
+}
+
+impl<S: Storage> Read for Recorder<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Storage> Write for Recorder<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let offset = self.inner.stream_position()?;
+        let mut old = self.read_existing(offset, buf.len() as u64)?;
+        self.inner.seek(SeekFrom::Start(offset))?;
+        let written = self.inner.write(buf)?;
+        old.truncate(written);
+        self.record_write(offset, old, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: Storage> Seek for Recorder<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Start and end of the bytes a move from `offset` to `destination` overwrites outside of the
+/// bytes it moves
+fn overwritten_range(offset: u64, destination: u64, length: u64) -> (u64, u64) {
+    match destination > offset {
+        true => ((offset + length).max(destination), destination + length),
+        false => (destination, (destination + length).min(offset)),
+    }
+}
+
+impl<S: Storage> Storage for Recorder<S> {
+    fn flen(&mut self) -> io::Result<u64> {
+        self.inner.flen()
+    }
+
+    fn fset_len(&mut self, len: u64) -> io::Result<()> {
+        let old_len = self.inner.flen()?;
+        if len == old_len {
+            return Ok(());
+        }
+        let position = self.inner.stream_position()?;
+        let removed = match len < old_len {
+            true => self.read_existing(len, old_len - len)?,
+            false => Vec::new(),
+        };
+        self.inner.seek(SeekFrom::Start(position))?;
+        self.inner.fset_len(len)?;
+        self.log.changes.push(Change::SetLen {
+            old_len,
+            new_len: len,
+            removed,
+        });
+        Ok(())
+    }
+
+    fn fcopy_range(&mut self, dst: u64, src: u64, len: u64) -> io::Result<bool> {
+        // grow the storage first, so that the move itself never changes its length
+        if dst + len > self.inner.flen()? {
+            self.fset_len(dst + len)?;
+        }
+        let position = self.inner.stream_position()?;
+        let (start, end) = overwritten_range(src, dst, len);
+        let overwritten = self.read_existing(start, end - start)?;
+        self.inner.fmemmove(dst, src, len)?;
+        self.inner.seek(SeekFrom::Start(position))?;
+        self.log.changes.push(Change::Move {
+            offset: src,
+            destination: dst,
+            length: len,
+            overwritten,
+        });
+        Ok(true)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl WriteLog {
+    /// Length of the storage after the last change
+    pub fn final_len(&self) -> u64 {
+        let mut len = self.original_len;
+        for change in &self.changes {
+            match change {
+                Change::Write { offset, new, .. } => len = len.max(offset + new.len() as u64),
+                Change::SetLen { new_len, .. } => len = *new_len,
+                Change::Move { .. } => (),
+            }
+        }
+        len
+    }
+
+    /// Undo every change on `storage`, which must hold the result of the logged changes
+    ///
+    /// Fails with `BadPatch` if it doesn't, which is noticed at the latest when a write is undone
+    /// whose bytes are not in `storage`, so `storage` may be partially reverted by then. The bytes
+    /// of a move are not checked, only the writes around it.
+    pub fn revert<S: Storage>(&self, storage: &mut S) -> Result<()> {
+        let not_the_output = || {
+            InsertDylibError::BadPatch("the binary is not the output of the write log".to_string())
+        };
+        if storage.flen()? != self.final_len() {
+            return Err(not_the_output());
+        }
+
+        for change in self.changes.iter().rev() {
+            match change {
+                Change::Write { offset, old, new } => {
+                    let mut current = vec![0u8; new.len()];
+                    storage.seek(SeekFrom::Start(*offset))?;
+                    storage.read_exact(&mut current)?;
+                    if current != *new {
+                        return Err(not_the_output());
+                    }
+                    storage.seek(SeekFrom::Start(*offset))?;
+                    storage.write_all(old)?;
+                    // the write ran past the end of the storage and grew it
+                    if old.len() < new.len() {
+                        storage.fset_len(offset + old.len() as u64)?;
+                    }
+                }
+                Change::SetLen {
+                    old_len,
+                    new_len,
+                    removed,
+                } => {
+                    storage.fset_len(*old_len)?;
+                    if !removed.is_empty() {
+                        storage.seek(SeekFrom::Start(*new_len))?;
+                        storage.write_all(removed)?;
+                    }
+                }
+                Change::Move {
+                    offset,
+                    destination,
+                    length,
+                    overwritten,
+                } => {
+                    let (start, end) = overwritten_range(*offset, *destination, *length);
+                    if overwritten.len() as u64 != end - start {
+                        return Err(not_the_output());
+                    }
+                    storage.fmemmove(*offset, *destination, *length)?;
+                    storage.seek(SeekFrom::Start(start))?;
+                    storage.write_all(overwritten)?;
+                }
+            }
+        }
+        // drop whatever writes past the original end appended
+        storage.fset_len(self.original_len)?;
+        Ok(())
+    }
+
+    /// Parse a log saved from `to_json`
+    pub fn from_json(value: &Value) -> Result<WriteLog> {
+        let bad = |what: &str| InsertDylibError::BadPatch(format!("bad write log, {}", what));
+        let u64_key = |value: &Value, key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| bad(&format!("missing {}", key)))
+        };
+        let hex_key = |value: &Value, key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .and_then(from_hex)
+                .ok_or_else(|| bad(&format!("{} is not hex", key)))
+        };
+
+        let version = u64_key(value, "version")?;
+        if version != WRITE_LOG_VERSION as u64 {
+            return Err(bad(&format!("unsupported version {}", version)));
+        }
+        let changes = value
+            .get("changes")
+            .and_then(Value::as_array)
+            .ok_or_else(|| bad("missing changes"))?
+            .iter()
+            .map(|change| match change.get("op").and_then(Value::as_str) {
+                Some("write") => Ok(Change::Write {
+                    offset: u64_key(change, "offset")?,
+                    old: hex_key(change, "old")?,
+                    new: hex_key(change, "new")?,
+                }),
+                Some("set_len") => Ok(Change::SetLen {
+                    old_len: u64_key(change, "old_len")?,
+                    new_len: u64_key(change, "new_len")?,
+                    removed: hex_key(change, "removed")?,
+                }),
+                Some("move") => Ok(Change::Move {
+                    offset: u64_key(change, "offset")?,
+                    destination: u64_key(change, "destination")?,
+                    length: u64_key(change, "length")?,
+                    overwritten: hex_key(change, "overwritten")?,
+                }),
+                _ => Err(bad("unknown op")),
+            })
+            .collect::<Result<Vec<Change>>>()?;
+
+        Ok(WriteLog {
+            original_len: u64_key(value, "original_len")?,
+            changes,
+        })
+    }
+
+    /// The log as a JSON document, the bytes are hex encoded
+    pub fn to_json(&self) -> Value {
+        let changes: Vec<Value> = self
+            .changes
+            .iter()
+            .map(|change| match change {
+                Change::Write { offset, old, new } => json!({
+                    "op": "write",
+                    "offset": offset,
+                    "length": new.len(),
+                    "old": to_hex(old),
+                    "new": to_hex(new),
+                }),
+                Change::SetLen {
+                    old_len,
+                    new_len,
+                    removed,
+                } => json!({
+                    "op": "set_len",
+                    "old_len": old_len,
+                    "new_len": new_len,
+                    "removed": to_hex(removed),
+                }),
+                Change::Move {
+                    offset,
+                    destination,
+                    length,
+                    overwritten,
+                } => json!({
+                    "op": "move",
+                    "offset": offset,
+                    "destination": destination,
+                    "length": length,
+                    "overwritten": to_hex(overwritten),
+                }),
+            })
+            .collect();
+        json!({
+            "version": WRITE_LOG_VERSION,
+            "original_len": self.original_len,
+            "final_len": self.final_len(),
+            "changes": changes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn recorded_changes() -> (Vec<u8>, Cursor<Vec<u8>>, WriteLog) {
+        let original: Vec<u8> = (0..64).collect();
+        let mut recorder = Recorder::new(Cursor::new(original.clone())).unwrap();
+        recorder.seek(SeekFrom::Start(8)).unwrap();
+        recorder.write_all(&[0xaa; 4]).unwrap();
+        recorder.write_all(&[0xbb; 4]).unwrap();
+        recorder.fset_len(40).unwrap();
+        recorder.seek(SeekFrom::Start(36)).unwrap();
+        recorder.write_all(&[0xcc; 12]).unwrap();
+        recorder.fset_len(80).unwrap();
+        let (patched, log) = recorder.into_parts();
+        (original, patched, log)
+    }
+
+    #[test]
+    fn json_roundtrip_reverts_to_original() {
+        let (original, mut patched, log) = recorded_changes();
+        assert_eq!(log.final_len(), 80);
+        // the two adjacent writes are merged
+        assert_eq!(log.changes.len(), 4);
+
+        let parsed = WriteLog::from_json(&log.to_json()).unwrap();
+        assert_eq!(parsed.original_len, log.original_len);
+        assert_eq!(parsed.changes, log.changes);
+
+        parsed.revert(&mut patched).unwrap();
+        assert_eq!(patched.into_inner(), original);
+    }
+
+    #[test]
+    fn revert_rejects_other_binaries() {
+        let (original, _, log) = recorded_changes();
+        let mut other = Cursor::new(original);
+        other.fset_len(80).unwrap();
+        assert!(matches!(
+            log.revert(&mut other),
+            Err(InsertDylibError::BadPatch(_))
+        ));
+    }
+
+    /// A binary with a slice of `SLICE_LEN` bytes at 0x1000
+    const SLICE_LEN: u64 = 1 << 20;
+
+    fn fat_binary() -> Vec<u8> {
+        (0..0x1000 + SLICE_LEN).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn slice_move_is_one_small_change() {
+        let original = fat_binary();
+        let mut recorder = Recorder::new(Cursor::new(original.clone())).unwrap();
+        recorder.fmemmove(0x2000, 0x1000, SLICE_LEN).unwrap();
+        recorder.seek(SeekFrom::Start(0x1000)).unwrap();
+        recorder.write_all(&[0; 0x1000]).unwrap();
+        let (mut patched, log) = recorder.into_parts();
+
+        assert_eq!(log.changes.len(), 3);
+        assert!(matches!(
+            &log.changes[1],
+            Change::Move {
+                offset: 0x1000,
+                destination: 0x2000,
+                length: SLICE_LEN,
+                overwritten,
+            } if overwritten.len() == 0x1000
+        ));
+        let log_len = log.to_json().to_string().len();
+        assert!(log_len < (SLICE_LEN / 16) as usize, "{} bytes", log_len);
+
+        let parsed = WriteLog::from_json(&log.to_json()).unwrap();
+        parsed.revert(&mut patched).unwrap();
+        assert!(patched.into_inner() == original);
+    }
+
+    #[test]
+    fn slice_move_back_reverts() {
+        let original = fat_binary();
+        let mut recorder = Recorder::new(Cursor::new(original.clone())).unwrap();
+        recorder.fmemmove(0x800, 0x1000, SLICE_LEN).unwrap();
+        recorder.fset_len(0x800 + SLICE_LEN).unwrap();
+        let (mut patched, log) = recorder.into_parts();
+
+        assert!(matches!(
+            &log.changes[0],
+            Change::Move { overwritten, .. } if overwritten.len() == 0x800
+        ));
+        log.revert(&mut patched).unwrap();
+        assert!(patched.into_inner() == original);
+    }
+
+    #[test]
+    fn from_json_rejects_bad_hex() {
+        let mut value = recorded_changes().2.to_json();
+        value["changes"][0]["old"] = Value::from("abc");
+        assert!(WriteLog::from_json(&value).is_err());
+    }
+}
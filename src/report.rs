@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// How results and messages are presented to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Messages are printed as they happen
+    Text,
+    /// Messages are collected and printed as a single JSON document at the end
+    Json,
+}
+
+/// Load command counts of a Mach-O header
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderCounts {
+    pub ncmds: u32,
+    pub sizeofcmds: u32,
+}
+
+/// What an operation did to a single slice
+#[derive(Debug, Clone)]
+pub struct SliceReport {
+    pub arch: String,
+    pub offset: u64,
+    pub before: HeaderCounts,
+    /// `None` if the operation failed
+    pub after: Option<HeaderCounts>,
+    pub error: Option<String>,
+    /// Warnings reported while the operation ran on this slice
+    pub warnings: Vec<String>,
+}
+
+/// Everything reported while running a command
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub messages: Vec<String>,
+    /// Warnings that don't belong to a single slice
+    pub warnings: Vec<String>,
+    pub slices: Vec<SliceReport>,
+}
+
+/// Where a command reports its progress, passed down to every operation
+///
+/// With `Format::Text` notes and warnings are printed as they happen, with `Format::Json` they are
+/// collected for the document printed at the end. Warnings are kept in both formats, so that the
+/// ones reported for a slice can be moved to its `SliceReport`.
+#[derive(Debug)]
+pub struct Reporter {
+    format: Format,
+    collected: Collected,
+}
+
+impl Reporter {
+    pub fn new(format: Format) -> Reporter {
+        Reporter {
+            format,
+            collected: Collected::default(),
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Tell the user what is going on
+    pub fn note(&mut self, message: impl fmt::Display) {
+        match self.format {
+            Format::Text => println!("{}", message),
+            Format::Json => self.collected.messages.push(message.to_string()),
+        }
+    }
+
+    /// Tell the user about something that may break the patched binary
+    pub fn warning(&mut self, message: impl fmt::Display) {
+        if self.format == Format::Text {
+            println!("Warning: {}", message);
+        }
+        self.collected.warnings.push(message.to_string());
+    }
+
+    /// Number of warnings reported so far, to take the ones reported after with `take_warnings`
+    pub fn warning_count(&self) -> usize {
+        self.collected.warnings.len()
+    }
+
+    /// Remove the warnings reported since there were `count` of them
+    pub fn take_warnings(&mut self, count: usize) -> Vec<String> {
+        self.collected
+            .warnings
+            .split_off(count.min(self.collected.warnings.len()))
+    }
+
+    /// Record the outcome of an operation on a slice, only kept for `Format::Json`
+    pub fn slice(&mut self, report: SliceReport) {
+        if self.format == Format::Json {
+            self.collected.slices.push(report);
+        }
+    }
+
+    /// Take everything collected so far
+    pub fn take(&mut self) -> Collected {
+        std::mem::take(&mut self.collected)
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
+/// Random access storage holding the binary being patched, either a file or a buffer in memory
+pub trait Storage: Read + Write + Seek {
+    /// Current length of the storage in bytes
+    fn flen(&mut self) -> io::Result<u64>;
+    /// Truncate or zero-extend the storage to `len` bytes
+    fn fset_len(&mut self, len: u64) -> io::Result<()>;
+    /// Copy `len` bytes from `src` to `dst` without a round trip through a user space buffer
+    ///
+    /// Returns `false` without touching the storage if there is no such fast path for these
+    /// ranges, in which case the caller has to copy them itself.
+    fn fcopy_range(&mut self, _dst: u64, _src: u64, _len: u64) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl Storage for File {
+    fn flen(&mut self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn fset_len(&mut self, len: u64) -> io::Result<()> {
+        self.set_len(len)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fcopy_range(&mut self, dst: u64, src: u64, len: u64) -> io::Result<bool> {
+        // the kernel refuses overlapping ranges within the same file
+        if src < dst + len && dst < src + len {
+            return Ok(false);
+        }
+
+        let fd = self.as_raw_fd();
+        let mut copied = 0u64;
+        while copied < len {
+            let mut off_in = (src + copied) as libc::loff_t;
+            let mut off_out = (dst + copied) as libc::loff_t;
+            let ret = unsafe {
+                libc::copy_file_range(
+                    fd,
+                    &mut off_in,
+                    fd,
+                    &mut off_out,
+                    (len - copied) as usize,
+                    0,
+                )
+            };
+            if ret < 0 {
+                let e = io::Error::last_os_error();
+                return match e.raw_os_error() {
+                    // not supported by the kernel or the filesystem, nothing has been copied yet
+                    Some(libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP)
+                        if copied == 0 =>
+                    {
+                        Ok(false)
+                    }
+                    _ => Err(e),
+                };
+            }
+            if ret == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            copied += ret as u64;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<T> Storage for Cursor<T>
+where
+    T: AsRef<[u8]> + AsMut<Vec<u8>>,
+    Cursor<T>: Write,
+{
+    fn flen(&mut self) -> io::Result<u64> {
+        Ok(self.get_ref().as_ref().len() as u64)
+    }
+
+    fn fset_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().as_mut().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn fcopy_range(&mut self, dst: u64, src: u64, len: u64) -> io::Result<bool> {
+        let buffer = self.get_mut().as_mut();
+        let (dst, src, len) = (dst as usize, src as usize, len as usize);
+        if src + len > buffer.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if dst + len > buffer.len() {
+            buffer.resize(dst + len, 0);
+        }
+        buffer.copy_within(src..src + len, dst);
+        Ok(true)
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &mut S {
+    fn flen(&mut self) -> io::Result<u64> {
+        (**self).flen()
+    }
+
+    fn fset_len(&mut self, len: u64) -> io::Result<()> {
+        (**self).fset_len(len)
+    }
+
+    fn fcopy_range(&mut self, dst: u64, src: u64, len: u64) -> io::Result<bool> {
+        (**self).fcopy_range(dst, src, len)
+    }
+}
@@ -1,77 +1,62 @@
-extern crate clap;
-use crate::opts::Opts;
-use clap::{App, Arg};
+use crate::macho::prelude::*;
 
-pub fn parse_arg() -> Opts {
-    let matches = App::new("Insert Dylib")
-        .version("0.2.0")
-        .author("Cocoa <i@uwucocoa.moe>")
-        .about("Insert dylib into Mach-O binary")
-        .arg(
-            Arg::with_name("dylib_path")
-                .short("d")
-                .long("dylib")
-                .required(true)
-                .help("dylib path")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("binary_path")
-                .short("b")
-                .long("binary")
-                .required(true)
-                .help("binary file")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("output")
-                .short("o")
-                .long("output")
-                .value_name("OUTPUT")
-                .required(false)
-                .help("output path")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("weak")
-                .long("weak")
-                .multiple(false)
-                .help("Sets LC_LOAD_WEAK_DYLIB"),
-        )
-        .arg(
-            Arg::with_name("overwrite")
-                .long("overwrite")
-                .multiple(false)
-                .help("Overwrite existent file"),
-        )
-        .arg(
-            Arg::with_name("strip_codesign")
-                .long("strip-codesign")
-                .multiple(false)
-                .help("Strip codesign"),
-        )
-        .arg(
-            Arg::with_name("all_yes")
-                .long("all-yes")
-                .multiple(false)
-                .help("Yes to all"),
-        )
-        .get_matches();
-
-    let mut options = Opts::default();
-    options.dylib_path = String::from(matches.value_of("dylib_path").unwrap());
-    options.binary_path = matches.value_of("binary_path").unwrap().into();
-
-    let mut default_output_path = String::new();
-    default_output_path.push_str(&options.binary_path);
-    default_output_path.push_str("_patched");
-    options.output_path = String::from(matches.value_of("OUTPUT").unwrap_or(&*default_output_path));
+pub fn load_command_name(cmd: u32) -> String {
+    let name = match cmd {
+        LC_SEGMENT => "LC_SEGMENT",
+        LC_SYMTAB => "LC_SYMTAB",
+        LC_SEGMENT_64 => "LC_SEGMENT_64",
+        LC_CODE_SIGNATURE => "LC_CODE_SIGNATURE",
+        LC_LOAD_DYLIB => "LC_LOAD_DYLIB",
+        LC_ID_DYLIB => "LC_ID_DYLIB",
+        LC_LOAD_WEAK_DYLIB => "LC_LOAD_WEAK_DYLIB",
+        LC_RPATH => "LC_RPATH",
+        LC_REEXPORT_DYLIB => "LC_REEXPORT_DYLIB",
+        LC_LOAD_UPWARD_DYLIB => "LC_LOAD_UPWARD_DYLIB",
+        LC_LAZY_LOAD_DYLIB => "LC_LAZY_LOAD_DYLIB",
+        LC_BUILD_VERSION => "LC_BUILD_VERSION",
+        LC_THREAD => "LC_THREAD",
+        LC_UNIXTHREAD => "LC_UNIXTHREAD",
+        LC_DYSYMTAB => "LC_DYSYMTAB",
+        LC_LOAD_DYLINKER => "LC_LOAD_DYLINKER",
+        LC_ID_DYLINKER => "LC_ID_DYLINKER",
+        LC_UUID => "LC_UUID",
+        LC_SEGMENT_SPLIT_INFO => "LC_SEGMENT_SPLIT_INFO",
+        LC_ENCRYPTION_INFO => "LC_ENCRYPTION_INFO",
+        LC_DYLD_INFO => "LC_DYLD_INFO",
+        LC_DYLD_INFO_ONLY => "LC_DYLD_INFO_ONLY",
+        LC_VERSION_MIN_MACOSX => "LC_VERSION_MIN_MACOSX",
+        LC_VERSION_MIN_IPHONEOS => "LC_VERSION_MIN_IPHONEOS",
+        LC_FUNCTION_STARTS => "LC_FUNCTION_STARTS",
+        LC_DYLD_ENVIRONMENT => "LC_DYLD_ENVIRONMENT",
+        LC_MAIN => "LC_MAIN",
+        LC_DATA_IN_CODE => "LC_DATA_IN_CODE",
+        LC_SOURCE_VERSION => "LC_SOURCE_VERSION",
+        LC_DYLIB_CODE_SIGN_DRS => "LC_DYLIB_CODE_SIGN_DRS",
+        LC_ENCRYPTION_INFO_64 => "LC_ENCRYPTION_INFO_64",
+        LC_LINKER_OPTION => "LC_LINKER_OPTION",
+        LC_LINKER_OPTIMIZATION_HINT => "LC_LINKER_OPTIMIZATION_HINT",
+        LC_VERSION_MIN_TVOS => "LC_VERSION_MIN_TVOS",
+        LC_VERSION_MIN_WATCHOS => "LC_VERSION_MIN_WATCHOS",
+        LC_NOTE => "LC_NOTE",
+        LC_DYLD_EXPORTS_TRIE => "LC_DYLD_EXPORTS_TRIE",
+        LC_DYLD_CHAINED_FIXUPS => "LC_DYLD_CHAINED_FIXUPS",
+        LC_FILESET_ENTRY => "LC_FILESET_ENTRY",
+        _ => return format!("0x{:x}", cmd),
+    };
+    name.to_string()
+}
 
-    options.weak = matches.occurrences_of("weak") == 1;
-    options.overwrite = matches.occurrences_of("overwrite") == 1;
-    options.strip_codesign = matches.occurrences_of("strip_codesign") == 1;
-    options.all_yes = matches.occurrences_of("all-yes") == 1;
-    options
+pub fn filetype_name(filetype: u32) -> String {
+    let name = match filetype {
+        MH_OBJECT => "object",
+        MH_EXECUTE => "executable",
+        MH_DYLIB => "dylib",
+        MH_DYLINKER => "dylinker",
+        MH_BUNDLE => "bundle",
+        MH_KEXT_BUNDLE => "kext bundle",
+        _ => return format!("filetype 0x{:x}", filetype),
+    };
+    name.to_string()
 }
 
 pub fn round_up_u64(x: u64, y: u64) -> u64 {
@@ -90,3 +75,39 @@ pub fn get_segname(segname: &[u8; 16]) -> String {
     }
     name
 }
+
+pub fn tool_name(tool: u32) -> String {
+    let name = match tool {
+        TOOL_CLANG => "clang",
+        TOOL_SWIFT => "swift",
+        TOOL_LD => "ld",
+        TOOL_LLD => "lld",
+        _ => return format!("tool {}", tool),
+    };
+    name.to_string()
+}
+
+/// Format an `a.b.c.d.e` version packed as 24.10.10.10.10 bits, as used by LC_SOURCE_VERSION
+pub fn format_source_version(version: u64) -> String {
+    format!(
+        "{}.{}.{}.{}.{}",
+        version >> 40,
+        (version >> 30) & 0x3ff,
+        (version >> 20) & 0x3ff,
+        (version >> 10) & 0x3ff,
+        version & 0x3ff
+    )
+}
+
+/// Format a UUID from LC_UUID like `otool -l`, e.g. `00010203-0405-0607-0809-0A0B0C0D0E0F`
+pub fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex: Vec<String> = uuid.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex[0..4].concat(),
+        hex[4..6].concat(),
+        hex[6..8].concat(),
+        hex[8..10].concat(),
+        hex[10..16].concat()
+    )
+}